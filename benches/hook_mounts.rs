@@ -0,0 +1,40 @@
+//! Mounting 1k `use_query` hooks with distinct keys, all served from the
+//! cache. Mostly measures the per-hook bookkeeping: building the key and
+//! registering it in the client's maps.
+//!
+//! Run with `cargo bench --bench hook_mounts`.
+
+use std::{rc::Rc, time::Instant};
+
+use sycamore::prelude::*;
+use sycamore_query::{prelude::*, ClientOptions, QueryClient};
+
+const HOOKS: u32 = 1000;
+const ROUNDS: u32 = 50;
+
+#[component(inline_props)]
+fn Todo<G: Html>(cx: Scope, id: u32) -> View<G> {
+    let Query { data, .. } = use_query(cx, ("todos", "detail", id), move || async move {
+        Ok::<_, ()>(id)
+    });
+    view! { cx, (data.get().as_ref().clone().ok().map_or(0, |id| *id)) }
+}
+
+fn main() {
+    let client = QueryClient::new(ClientOptions::default());
+    for id in 0..HOOKS {
+        client.set_query_data(("todos", "detail", id), id);
+    }
+    let mut total = std::time::Duration::ZERO;
+    for _ in 0..ROUNDS {
+        let client = Rc::clone(&client);
+        let start = Instant::now();
+        let html = sycamore::render_to_string(move |cx| {
+            provide_context(cx, client);
+            View::new_fragment((0..HOOKS).map(|id| view! { cx, Todo(id=id) }).collect())
+        });
+        total += start.elapsed();
+        assert!(html.contains(&(HOOKS - 1).to_string()));
+    }
+    println!("{HOOKS} hook mounts: {:?}", total / ROUNDS);
+}
@@ -0,0 +1,74 @@
+//! Invalidating a family of queries on a cache with 10k keys.
+//!
+//! Prefix lookups go through a sorted index, so their cost depends on the
+//! number of matching keys rather than the size of the cache. The baseline
+//! is the linear scan over every key that each lookup used to do.
+//!
+//! Run with `cargo bench --bench prefix_invalidation`.
+
+use std::{
+    hint::black_box,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use sycamore_query::{AsKeys, ClientOptions, QueryClient, QueryKey};
+
+const FAMILIES: u32 = 1000;
+const PER_FAMILY: u32 = 10;
+const ROUNDS: u32 = 20;
+
+fn populate(client: &QueryClient) {
+    for family in 0..FAMILIES {
+        for id in 0..PER_FAMILY {
+            client.set_query_data(("todos", family, id), id);
+        }
+    }
+}
+
+/// Time `f` once per family, repopulating the cache before each round.
+fn bench(name: &str, f: impl Fn(&Rc<QueryClient>, QueryKey)) -> Duration {
+    let client = QueryClient::new(ClientOptions::default());
+    let mut total = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        populate(&client);
+        let start = Instant::now();
+        for family in 0..FAMILIES {
+            f(&client, ("todos", family).as_keys());
+        }
+        total += start.elapsed();
+    }
+    let per_op = total / (ROUNDS * FAMILIES);
+    println!("{name:<24} {per_op:>12?} per prefix");
+    per_op
+}
+
+fn main() {
+    let keys: Vec<QueryKey> = (0..FAMILIES)
+        .flat_map(|family| (0..PER_FAMILY).map(move |id| ("todos", family, id).as_keys()))
+        .collect();
+    println!("{} keys, {PER_FAMILY} per prefix", keys.len());
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for family in 0..FAMILIES {
+            let prefix = ("todos", family).as_keys();
+            let matching = keys.iter().filter(|key| key.starts_with(&prefix)).count();
+            black_box(matching);
+        }
+    }
+    let scan = start.elapsed() / (ROUNDS * FAMILIES);
+    println!("{:<24} {scan:>12?} per prefix", "linear scan (baseline)");
+
+    let remove = bench("remove_queries", |client, prefix| {
+        client.remove_queries(prefix)
+    });
+    let invalidate = bench("invalidate_queries", |client, prefix| {
+        client.invalidate_queries(prefix)
+    });
+    println!(
+        "remove_queries is {:.1}x faster than the baseline scan alone, invalidate_queries {:.1}x",
+        scan.as_secs_f64() / remove.as_secs_f64(),
+        scan.as_secs_f64() / invalidate.as_secs_f64(),
+    );
+}
@@ -1,56 +1,658 @@
-use crate::client::ClientOptions;
-use fluvio_wasm_timer::Instant;
+use crate::timer::{Clock, SystemClock};
+use crate::{
+    client::{CacheEntryInfo, CacheStats, ClientOptions},
+    DataSignal, QueryData, QueryKey, Status,
+};
 use fnv::FnvHashMap;
-use std::{any::Any, rc::Rc, time::Duration};
+use std::{
+    any::{Any, TypeId},
+    cell::{Cell, RefCell},
+    collections::{BTreeSet, VecDeque},
+    future::Future,
+    mem::size_of_val,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use sycamore::reactive::{create_rc_signal, RcSignal};
 
-type Cache = FnvHashMap<Vec<u64>, CacheEntry>;
+type Cache = FnvHashMap<QueryKey, CacheEntry>;
+/// Estimates the size of a type erased value, see
+/// [`SizeHint`](crate::SizeHint).
+pub type SizeFn = Rc<dyn Fn(&dyn Any) -> usize>;
+pub type MutationFn = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()>>>>;
+
+/// The clock the caches read the time from, see
+/// [`ClientOptions::clock`](crate::ClientOptions::clock).
+#[derive(Clone)]
+pub struct CacheClock(pub Rc<dyn Clock>);
+
+impl Default for CacheClock {
+    fn default() -> Self {
+        Self(Rc::new(SystemClock))
+    }
+}
+
+impl CacheClock {
+    fn now(&self) -> Duration {
+        self.0.now()
+    }
+}
 
 pub struct CacheEntry {
-    created_at: Instant,
+    created_at: Duration,
+    /// How old the data already was when it was inserted, for hydrated data.
+    /// Kept separately because the clock can't go back further than its
+    /// starting point, which is the page load on the web.
+    initial_age: Duration,
     lifetime: Duration,
+    /// `None` while the query has observers. Once the last one is gone, the
+    /// entry expires `lifetime` later.
+    expires_at: Option<Duration>,
     value: Rc<dyn Any>,
+    /// The estimated size of `value` in bytes
+    size: usize,
+    /// When the entry was last inserted or looked up, for LRU eviction
+    last_used: Cell<Duration>,
+}
+
+impl CacheEntry {
+    fn age(&self, now: Duration) -> Duration {
+        now.saturating_sub(self.created_at) + self.initial_age
+    }
+
+    fn expired(&self, now: Duration) -> bool {
+        self.expires_at.is_some_and(|at| now > at)
+    }
+
+    fn live(&self, now: Duration) -> Option<Rc<dyn Any>> {
+        (!self.expired(now)).then(|| self.value.clone())
+    }
+}
+
+/// The hooks observing a key, see [`QueryCache::observe`].
+#[derive(Default)]
+struct Observers {
+    count: usize,
+    /// The longest `cache_expiration` of the hooks that observed the key since
+    /// it was last unused
+    lifetime: Duration,
+}
+
+/// A set of keys sorted so that all keys starting with the same prefix are
+/// next to each other. Finding them is a binary search instead of a scan over
+/// every key.
+#[derive(Default)]
+pub struct PrefixIndex(BTreeSet<QueryKey>);
+
+impl PrefixIndex {
+    pub fn insert(&mut self, key: QueryKey) {
+        self.0.insert(key);
+    }
+
+    pub fn remove(&mut self, key: &QueryKey) {
+        self.0.remove(key);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn retain(&mut self, keep: impl FnMut(&QueryKey) -> bool) {
+        self.0.retain(keep);
+    }
+
+    /// All keys starting with `prefix`. A prefix sorts before every key
+    /// starting with it, and those keys sort before any other key that
+    /// doesn't.
+    pub fn matching<'a>(&'a self, prefix: &'a QueryKey) -> impl Iterator<Item = &'a QueryKey> {
+        self.0
+            .range(prefix..)
+            .take_while(move |key| key.starts_with(prefix))
+    }
+
+    /// All keys starting with any of `prefixes`, without duplicates.
+    pub fn matching_any(&self, prefixes: &[QueryKey]) -> Vec<QueryKey> {
+        let mut keys: Vec<_> = prefixes
+            .iter()
+            .flat_map(|prefix| self.matching(prefix).cloned())
+            .collect();
+        if prefixes.len() > 1 {
+            keys.sort();
+            keys.dedup();
+        }
+        keys
+    }
 }
 
 #[derive(Default)]
 pub struct QueryCache {
     inner: Cache,
+    /// The keys of `inner`, for prefix lookups
+    index: PrefixIndex,
+    /// The mounted hooks using each key
+    observers: FnvHashMap<QueryKey, Observers>,
+    hits: Cell<u64>,
+    misses: Cell<u64>,
+    /// Size estimates by value type, used instead of `size_of_val`
+    size_hints: FnvHashMap<TypeId, SizeFn>,
+    /// Keys evicted to stay within `max_bytes` that haven't been reported
+    /// yet, see [`take_evicted`](QueryCache::take_evicted)
+    evicted: Vec<QueryKey>,
+    clock: CacheClock,
 }
 
 impl QueryCache {
-    pub fn get(&self, id: &[u64]) -> Option<Rc<dyn Any>> {
-        let entry = self.inner.get(id)?;
-        let age = Instant::now().duration_since(entry.created_at);
-        if age > entry.lifetime {
-            None
-        } else {
-            Some(entry.value.clone())
+    pub fn with_clock(clock: CacheClock) -> Self {
+        Self {
+            clock,
+            ..Default::default()
+        }
+    }
+
+    /// Look up a live entry, counting the lookup as a hit or miss.
+    pub fn get(&self, id: &QueryKey) -> Option<Rc<dyn Any>> {
+        let now = self.clock.now();
+        let entry = self.inner.get(id);
+        if let Some(entry) = entry {
+            entry.last_used.set(now);
         }
+        let value = entry.and_then(|entry| entry.live(now));
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.set(counter.get() + 1);
+        value
+    }
+
+    /// Whether the entry for `id` is older than `stale_time`. Missing entries
+    /// are always stale.
+    pub fn is_stale(&self, id: &QueryKey, stale_time: Duration) -> bool {
+        let now = self.clock.now();
+        self.inner
+            .get(id)
+            .is_none_or(|entry| entry.age(now) >= stale_time)
     }
 
     pub fn insert(
         &mut self,
-        id: Vec<u64>,
+        id: QueryKey,
         value: Rc<dyn Any>,
         options: &ClientOptions,
     ) -> Rc<dyn Any> {
+        self.insert_with_age(id, value, Duration::ZERO, options)
+    }
+
+    /// Insert data that was fetched `age` ago, so it expires and goes stale
+    /// when it would have if it had been fetched by this cache.
+    pub fn insert_with_age(
+        &mut self,
+        id: QueryKey,
+        value: Rc<dyn Any>,
+        age: Duration,
+        options: &ClientOptions,
+    ) -> Rc<dyn Any> {
+        let now = self.clock.now();
+        let lifetime = options.cache_expiration;
+        let expires_at =
+            (!self.observers.contains_key(&id)).then(|| now + lifetime.saturating_sub(age));
+        let size = self.size_of(&value);
+        self.index.insert(id.clone());
         self.inner.insert(
-            id,
+            id.clone(),
             CacheEntry {
-                created_at: Instant::now(),
-                lifetime: options.cache_expiration,
+                created_at: now,
+                initial_age: age,
+                lifetime,
+                expires_at,
                 value: value.clone(),
+                size,
+                last_used: Cell::new(now),
             },
         );
+        self.evict(&id, options);
         value
     }
 
-    pub fn invalidate_keys(&mut self, keys: &[&[u64]]) {
+    /// Estimate the size of `value` in bytes.
+    fn size_of(&self, value: &Rc<dyn Any>) -> usize {
+        let value = &**value;
+        match self.size_hints.get(&<dyn Any>::type_id(value)) {
+            Some(size_hint) => size_hint(value),
+            None => size_of_val(value),
+        }
+    }
+
+    /// Use `size_hint` to estimate the size of values of type `type_id`.
+    pub fn register_size_hint(&mut self, type_id: TypeId, size_hint: SizeFn) {
+        self.size_hints.insert(type_id, size_hint);
+    }
+
+    /// Evict the least recently used entries until the cache fits into
+    /// [`ClientOptions::max_bytes`]. Entries with observers and the entry for
+    /// `inserted` are never evicted.
+    fn evict(&mut self, inserted: &QueryKey, options: &ClientOptions) {
+        let Some(max_bytes) = options.max_bytes else {
+            return;
+        };
+        let mut total: usize = self.inner.values().map(|entry| entry.size).sum();
+        while total > max_bytes {
+            let lru = self
+                .inner
+                .iter()
+                .filter(|(key, _)| *key != inserted && !self.observers.contains_key(key))
+                .min_by_key(|(_, entry)| entry.last_used.get())
+                .map(|(key, _)| key.clone());
+            let Some(key) = lru else {
+                break;
+            };
+            if let Some(entry) = self.inner.remove(&key) {
+                total -= entry.size;
+            }
+            self.index.remove(&key);
+            self.evicted.push(key);
+        }
+    }
+
+    /// The keys evicted since the last call.
+    pub fn take_evicted(&mut self) -> Vec<QueryKey> {
+        std::mem::take(&mut self.evicted)
+    }
+
+    /// Register a hook using `id` that wants the entry kept for `lifetime`
+    /// after it's no longer used. Observed entries don't expire.
+    pub fn observe(&mut self, id: &QueryKey, lifetime: Duration) {
+        let observers = self.observers.entry(id.clone()).or_default();
+        observers.count += 1;
+        observers.lifetime = observers.lifetime.max(lifetime);
+        let now = self.clock.now();
+        if self.inner.get(id).is_some_and(|entry| entry.expired(now)) {
+            self.remove(id);
+        }
+        if let Some(entry) = self.inner.get_mut(id) {
+            entry.expires_at = None;
+        }
+    }
+
+    /// Unregister a hook using `id`. When the last one is gone, the entry
+    /// starts expiring from this moment, after the longest lifetime any of
+    /// the hooks asked for.
+    pub fn unobserve(&mut self, id: &QueryKey) {
+        let Some(observers) = self.observers.get_mut(id) else {
+            return;
+        };
+        observers.count -= 1;
+        if observers.count > 0 {
+            return;
+        }
+        let lifetime = observers.lifetime;
+        self.observers.remove(id);
+        if let Some(entry) = self.inner.get_mut(id) {
+            entry.lifetime = lifetime;
+            entry.expires_at = Some(self.clock.now() + lifetime);
+        }
+    }
+
+    pub fn observer_count(&self, id: &QueryKey) -> usize {
+        self.observers
+            .get(id)
+            .map_or(0, |observers| observers.count)
+    }
+
+    /// Replace the value of an entry without touching its timestamp. Inserts a
+    /// new entry if there is none.
+    pub fn replace(&mut self, id: QueryKey, value: Rc<dyn Any>, options: &ClientOptions) {
+        let size = self.size_of(&value);
+        match self.inner.get_mut(&id) {
+            Some(entry) => {
+                entry.value = value;
+                entry.size = size;
+                entry.last_used.set(self.clock.now());
+                self.evict(&id, options);
+            }
+            None => {
+                self.insert(id, value, options);
+            }
+        }
+    }
+
+    /// All entries that haven't expired and whose keys start with `prefix`.
+    pub fn matching(&self, prefix: &QueryKey) -> Vec<(QueryKey, Rc<dyn Any>)> {
+        let now = self.clock.now();
+        self.index
+            .matching(prefix)
+            .filter_map(|key| Some((key.clone(), self.inner[key].live(now)?)))
+            .collect()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.inner.len(),
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+            bytes_estimate: self.inner.values().map(|entry| entry.size).sum(),
+        }
+    }
+
+    /// A snapshot of all live entries, stale if they're older than
+    /// `stale_time`.
+    pub fn entries(&self, stale_time: Duration) -> Vec<CacheEntryInfo> {
+        let now = self.clock.now();
         self.inner
-            .retain(|key, _| !keys.iter().any(|&prefix| key.starts_with(prefix)));
+            .iter()
+            .filter(|(_, entry)| !entry.expired(now))
+            .map(|(key, entry)| CacheEntryInfo {
+                key: key.clone(),
+                age: entry.age(now),
+                lifetime: entry.lifetime,
+                is_stale: entry.age(now) >= stale_time,
+            })
+            .collect()
+    }
+
+    pub fn reset_stats(&self) {
+        self.hits.set(0);
+        self.misses.set(0);
     }
 
-    pub fn collect_garbage(&mut self) {
+    pub fn contains_key(&self, id: &QueryKey) -> bool {
+        self.inner.contains_key(id)
+    }
+
+    /// Remove the entry for `id`, returning whether there was one.
+    pub fn remove(&mut self, id: &QueryKey) -> bool {
+        self.index.remove(id);
+        self.inner.remove(id).is_some()
+    }
+
+    /// Remove all entries, returning their keys.
+    pub fn clear(&mut self) -> Vec<QueryKey> {
+        self.index.clear();
+        self.inner.drain().map(|(key, _)| key).collect()
+    }
+
+    /// The keys of all entries, including expired ones that haven't been
+    /// collected yet.
+    #[cfg(feature = "devtools")]
+    pub fn keys(&self) -> impl Iterator<Item = &QueryKey> {
+        self.inner.keys()
+    }
+
+    /// How old the data for `id` is, if there is any.
+    #[cfg(feature = "devtools")]
+    pub fn age(&self, id: &QueryKey) -> Option<Duration> {
+        self.inner.get(id).map(|entry| entry.age(self.clock.now()))
+    }
+
+    /// Remove all entries whose keys start with any of `keys`, returning the
+    /// keys of the removed entries.
+    pub fn invalidate_keys(&mut self, keys: &[QueryKey]) -> Vec<QueryKey> {
+        let removed = self.index.matching_any(keys);
+        for key in &removed {
+            self.remove(key);
+        }
+        removed
+    }
+
+    /// Remove expired entries, returning their keys.
+    pub fn collect_garbage(&mut self) -> Vec<QueryKey> {
+        let now = self.clock.now();
+        self.remove_where(|_, entry| entry.expired(now))
+    }
+
+    fn remove_where(&mut self, remove: impl Fn(&QueryKey, &CacheEntry) -> bool) -> Vec<QueryKey> {
+        let mut removed = Vec::new();
+        self.inner.retain(|key, entry| {
+            let keep = !remove(key, entry);
+            if !keep {
+                removed.push(key.clone());
+            }
+            keep
+        });
+        for key in &removed {
+            self.index.remove(key);
+        }
+        removed
+    }
+}
+
+/// A mutation tracked by the client. The state is kept in signals so it can
+/// be observed after the component that started the mutation has unmounted.
+pub struct MutationEntry {
+    pub id: u64,
+    pub key: Option<QueryKey>,
+    pub data: Rc<DataSignal>,
+    pub status: Rc<RcSignal<Status>>,
+    paused: Option<MutationFn>,
+    settled_at: Option<Duration>,
+}
+
+#[derive(Default)]
+pub struct MutationCache {
+    next_id: u64,
+    inner: Vec<MutationEntry>,
+    clock: CacheClock,
+}
+
+impl MutationCache {
+    pub fn with_clock(clock: CacheClock) -> Self {
+        Self {
+            clock,
+            ..Default::default()
+        }
+    }
+
+    pub fn insert(&mut self, key: Option<QueryKey>) -> &MutationEntry {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.inner.push(MutationEntry {
+            id,
+            key,
+            data: Rc::new(create_rc_signal(QueryData::Loading)),
+            status: Rc::new(create_rc_signal(Status::Fetching)),
+            paused: None,
+            settled_at: None,
+        });
+        self.inner.last().unwrap()
+    }
+
+    /// Queue a mutation to be run by [`next_paused`](MutationCache::next_paused).
+    pub fn pause(&mut self, id: u64, mutation: MutationFn) {
+        if let Some(entry) = self.inner.iter_mut().find(|entry| entry.id == id) {
+            entry.paused = Some(mutation);
+        }
+    }
+
+    /// Take the oldest paused mutation out of the queue.
+    pub fn next_paused(&mut self) -> Option<MutationFn> {
         self.inner
-            .retain(|_, entry| Instant::now().duration_since(entry.created_at) < entry.lifetime);
+            .iter_mut()
+            .find(|entry| entry.paused.is_some())?
+            .paused
+            .take()
+    }
+
+    pub fn paused_count(&self) -> usize {
+        self.inner
+            .iter()
+            .filter(|entry| entry.paused.is_some())
+            .count()
+    }
+
+    pub fn settle(&mut self, id: u64) {
+        if let Some(entry) = self.inner.iter_mut().find(|entry| entry.id == id) {
+            entry.settled_at = Some(self.clock.now());
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MutationEntry> {
+        self.inner.iter()
+    }
+
+    pub fn collect_garbage(&mut self, lifetime: Duration) {
+        let now = self.clock.now();
+        self.inner.retain(|entry| {
+            entry
+                .settled_at
+                .is_none_or(|settled_at| now.saturating_sub(settled_at) < lifetime)
+        });
+    }
+}
+
+/// A future that resolves once the fetch it was created for has settled.
+/// Every clone resolves, so several callers can wait for the same fetch.
+#[derive(Clone, Default)]
+pub struct InFlight(Rc<RefCell<InFlightState>>);
+
+#[derive(Default)]
+struct InFlightState {
+    settled: bool,
+    wakers: Vec<Waker>,
+}
+
+impl InFlight {
+    /// An already settled fetch, for when nothing needed to be fetched.
+    pub fn settled() -> Self {
+        let in_flight = Self::default();
+        in_flight.settle();
+        in_flight
+    }
+
+    pub fn settle(&self) {
+        let wakers = {
+            let mut state = self.0.borrow_mut();
+            state.settled = true;
+            std::mem::take(&mut state.wakers)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+impl Future for InFlight {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.0.borrow_mut();
+        if state.settled {
+            return Poll::Ready(());
+        }
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Limits how many fetches run at once. Fetches waiting for a slot get one in
+/// the order they asked for it.
+#[derive(Clone, Default)]
+pub struct FetchLimit(Rc<RefCell<FetchLimitState>>);
+
+#[derive(Default)]
+struct FetchLimitState {
+    max: Option<usize>,
+    running: usize,
+    next_ticket: u64,
+    /// Waiting fetches by ticket, oldest first
+    queue: VecDeque<(u64, Option<Waker>)>,
+}
+
+impl FetchLimitState {
+    fn wake_next(&mut self) {
+        if let Some(waker) = self.queue.front_mut().and_then(|(_, waker)| waker.take()) {
+            waker.wake();
+        }
+    }
+}
+
+impl FetchLimit {
+    pub fn new(max: Option<usize>) -> Self {
+        Self(Rc::new(RefCell::new(FetchLimitState {
+            max,
+            ..Default::default()
+        })))
+    }
+
+    /// Wait for a slot. The slot is freed when the returned [`FetchPermit`]
+    /// is dropped. The place in the queue is taken right away, not on the
+    /// first poll.
+    pub fn acquire(&self) -> AcquireFetch {
+        let mut state = self.0.borrow_mut();
+        let ticket = state.max.map(|_| {
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.queue.push_back((ticket, None));
+            ticket
+        });
+        AcquireFetch {
+            limit: self.clone(),
+            ticket,
+        }
+    }
+}
+
+/// The future returned by [`FetchLimit::acquire`]. Leaves the queue when
+/// dropped before it got a slot.
+pub struct AcquireFetch {
+    limit: FetchLimit,
+    /// `None` once a slot was granted, or if there is no limit
+    ticket: Option<u64>,
+}
+
+impl Future for AcquireFetch {
+    type Output = FetchPermit;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<FetchPermit> {
+        let Some(ticket) = self.ticket else {
+            return Poll::Ready(FetchPermit(None));
+        };
+        let mut state = self.limit.0.borrow_mut();
+        let max = state.max.unwrap_or(usize::MAX);
+        let first = state.queue.front().is_some_and(|(t, _)| *t == ticket);
+        if first && state.running < max {
+            state.queue.pop_front();
+            state.running += 1;
+            // There might be room for the next one as well
+            if state.running < max {
+                state.wake_next();
+            }
+            drop(state);
+            self.ticket = None;
+            return Poll::Ready(FetchPermit(Some(self.limit.clone())));
+        }
+        if let Some((_, waker)) = state.queue.iter_mut().find(|(t, _)| *t == ticket) {
+            *waker = Some(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for AcquireFetch {
+    fn drop(&mut self) {
+        let Some(ticket) = self.ticket else {
+            return;
+        };
+        let mut state = self.limit.0.borrow_mut();
+        let first = state.queue.front().is_some_and(|(t, _)| *t == ticket);
+        state.queue.retain(|(t, _)| *t != ticket);
+        if first {
+            state.wake_next();
+        }
+    }
+}
+
+/// A running fetch's slot, freed when dropped. See [`FetchLimit`].
+pub struct FetchPermit(Option<FetchLimit>);
+
+impl Drop for FetchPermit {
+    fn drop(&mut self) {
+        if let Some(limit) = &self.0 {
+            let mut state = limit.0.borrow_mut();
+            state.running -= 1;
+            state.wake_next();
+        }
     }
 }
@@ -1,13 +1,31 @@
 use fnv::{FnvBuildHasher, FnvHashMap};
 use std::{
+    any::{type_name, Any},
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    future::Future,
+    pin::Pin,
     rc::{Rc, Weak},
     sync::RwLock,
     time::Duration,
 };
-use sycamore::reactive::Signal;
+use sycamore::reactive::{
+    create_effect, create_rc_signal, try_use_context, RcSignal, ReadSignal, Scope,
+};
 use weak_table::WeakValueHashMap;
 
-use crate::{cache::QueryCache, AsKeys, DataSignal, Fetcher, QueryData, Status};
+use crate::{
+    cache::{CacheClock, FetchLimit, InFlight, MutationCache, PrefixIndex, QueryCache},
+    focus::{FocusManager, WindowFocus},
+    logger::{LogLogger, Logger},
+    mutation::MutationState,
+    online::{NavigatorOnline, OnlineManager},
+    timer::{self, Clock, SystemClock},
+    AsKeys, DataSource, ErasedData, FetchFuture, Fetcher, IntoKeys, Listeners, QueryData, QueryKey,
+    StateSignal, Status, Subscription,
+};
 
 /// Global query options.
 /// These can be overridden on a per query basis with [`QueryOptions`].
@@ -15,71 +33,707 @@ use crate::{cache::QueryCache, AsKeys, DataSignal, Fetcher, QueryData, Status};
 /// # Options
 ///
 /// * `cache_expiration` - The time before a cached query result expires.
-/// Default: 5 minutes
+///   The clock starts once no mounted query uses the result anymore, or when
+///   it's cached if no query uses it at all. Default: 5 minutes
+/// * `stale_time` - The time after which cached data is considered stale.
+///   Mounting a query with fresh data in the cache serves the cached data
+///   without refetching. Default: 0, so cached data is always refetched
 /// * `retries` - The number of times to retry a query if it fails. Default: 3
+/// * `background_retries` - The number of times to retry a background
+///   refetch, meaning a fetch for a query that already shows data. Nobody is
+///   waiting on those, so fewer retries avoid hammering a struggling server.
+///   Default: `None`, which uses `retries`
 /// * `retry_fn` - The function for the timeout between retries. Defaults to
-/// exponential delay starting with 1 second, but not going over 30 seconds.
+///   exponential delay starting with 1 second, but not going over 30 seconds.
+///   See [`RetryDelay`] for common alternatives.
+/// * `refetch_on_window_focus` - Refetch mounted queries with stale data when
+///   the app regains focus. Default: `false`
+/// * `focus_manager` - Decides whether the app is focused, see
+///   [`FocusManager`]. Default: [`WindowFocus`]
+/// * `online_manager` - Decides whether the network is available, see
+///   [`OnlineManager`]. Default: [`NavigatorOnline`]
+/// * `logger` - Receives query lifecycle events, see [`Logger`]. Default:
+///   [`LogLogger`] in debug builds, `None` in release builds
+/// * `ssr` - Whether the client is used for server side rendering. Queries
+///   then never fetch and only serve what's already cached, for example
+///   through [`QueryClient::hydrate_query_data`], and the client doesn't
+///   listen to browser events. Default: `true` when there's no browser
+///   `window`, unless the `tokio` or `async-std` feature is enabled to run
+///   queries on native targets
+/// * `max_bytes` - A budget for the estimated size of all cached data. When
+///   it's exceeded, the least recently used data that no mounted query uses
+///   is evicted. See [`SizeHint`] for how sizes are estimated. Default:
+///   `None`, so only expiration removes data
+/// * `clock` - The source of the current time for expiration, staleness and
+///   eviction, see [`Clock`]. Default: [`SystemClock`]
+/// * `skip_delays` - Retry immediately, don't debounce key changes and don't
+///   run refetch intervals, except under a `TestQueryClient`. Meant for
+///   tests, see [`test_defaults`](ClientOptions::test_defaults). Default:
+///   `false`
+/// * `on_query_success` / `on_query_error` - Called with the key, the
+///   query's metadata (see [`QueryOptions::meta`]) and the type erased data
+///   or error every time a query fetch settles, including background
+///   refetches and prefetches. Use `downcast` on the value to
+///   handle known types, for example to show a toast for every error.
+///   Default: `None`
+/// * `on_mutation_success` / `on_mutation_error` - The same for mutations,
+///   called after the mutation's own `on_success` and with its
+///   `mutation_key`, if any. Default: `None`
+/// * `max_concurrent_fetches` - The most query fetches to run at once,
+///   including their retries. Further fetches wait in the order they were
+///   started, with their status already [`Status::Fetching`]. Useful for
+///   pages with many queries against a rate limited API. Default: `None`, so
+///   all fetches start right away
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::{*, focus::ManualFocus};
+/// # use std::rc::Rc;
+/// let focus = ManualFocus::default();
+/// let client = QueryClient::new(ClientOptions {
+///     refetch_on_window_focus: true,
+///     focus_manager: Rc::new(focus.clone()),
+///     ..Default::default()
+/// });
+/// // Stale queries are refetched when focus comes back
+/// focus.set_focused(false);
+/// focus.set_focused(true);
+/// ```
+///
+/// With `max_concurrent_fetches`, queued fetches start as running ones
+/// settle:
 ///
+/// ```
+/// # #[cfg(feature = "test-util")] {
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, testing::TestQueryClient, *};
+/// # async fn fetch_widget(id: u32) -> Result<String, String> { unreachable!() }
+/// let test = TestQueryClient::with_options(ClientOptions {
+///     max_concurrent_fetches: Some(2),
+///     ..ClientOptions::test_defaults()
+/// });
+/// create_scope_immediate(|cx| {
+///     provide_context(cx, test.client());
+///     let widgets: Vec<_> = (0..5)
+///         .map(|id| use_query(cx, ("widget", id), move || fetch_widget(id)))
+///         .collect();
+///     assert!(widgets.iter().all(|widget| *widget.status.get() == Status::Fetching));
+///
+///     for id in 0..5 {
+///         let pending = test.pending_fetches();
+///         assert_eq!(pending.len(), 2.min(5 - id as usize));
+///         assert_eq!(pending[0], ("widget", id).as_keys());
+///         test.resolve(("widget", id), format!("Widget {id}"));
+///     }
+///     assert!(test.pending_fetches().is_empty());
+/// });
+/// # }
+/// ```
 #[derive(Clone)]
 pub struct ClientOptions {
-    /// The time before a cached query result expires. Default: 5 minutes
+    /// The time before a cached query result expires, counted from when the
+    /// last query using it unmounts. Default: 5 minutes
     pub cache_expiration: Duration,
+    /// The time after which cached data is considered stale. Default: 0
+    pub stale_time: Duration,
     /// The number of times to retry a query if it fails. Default: 3
     pub retries: u32,
+    /// The number of times to retry a refetch of a query that already shows
+    /// data. Default: `None`, which uses `retries`
+    pub background_retries: Option<u32>,
     /// The function for the timeout between retries. Defaults to
     /// exponential delay starting with 1 second, but not going over 30 seconds.
     pub retry_fn: Rc<dyn Fn(u32) -> Duration>,
+    /// Refetch mounted queries with stale data when the app regains focus.
+    /// Default: `false`
+    pub refetch_on_window_focus: bool,
+    /// Decides whether the app is focused. Default: [`WindowFocus`]
+    pub focus_manager: Rc<dyn FocusManager>,
+    /// Decides whether the network is available. Default: [`NavigatorOnline`]
+    pub online_manager: Rc<dyn OnlineManager>,
+    /// Receives query lifecycle events. Default: [`LogLogger`] in debug
+    /// builds, `None` in release builds
+    pub logger: Option<Rc<dyn Logger>>,
+    /// Whether the client is used for server side rendering. Default: `true`
+    /// when there's no browser `window` and neither the `tokio` nor the
+    /// `async-std` feature is enabled
+    pub ssr: bool,
+    /// A budget for the estimated size of all cached data in bytes.
+    /// Default: `None`
+    pub max_bytes: Option<usize>,
+    /// The source of the current time for the caches. Default:
+    /// [`SystemClock`]
+    pub clock: Rc<dyn Clock>,
+    /// Skip all delays and don't run refetch intervals. Default: `false`
+    pub skip_delays: bool,
+    /// Called with the key, metadata and data every time a query fetch
+    /// succeeds. Default: `None`
+    pub on_query_success: Option<QueryHandler>,
+    /// Called with the key, metadata and error every time a query fetch fails
+    /// after all retries. Default: `None`
+    pub on_query_error: Option<QueryHandler>,
+    /// Called with the data every time a mutation succeeds. Default: `None`
+    pub on_mutation_success: Option<MutationHandler>,
+    /// Called with the error every time a mutation fails. Default: `None`
+    pub on_mutation_error: Option<MutationHandler>,
+    /// The most query fetches to run at once. Default: `None`
+    pub max_concurrent_fetches: Option<usize>,
 }
 
+/// A global handler for settled queries, see
+/// [`ClientOptions::on_query_success`].
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "test-util")] {
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, testing::TestQueryClient, *};
+/// # use std::{cell::RefCell, rc::Rc};
+/// # async fn fetch_user(id: u32) -> Result<String, String> { unreachable!() }
+/// let toasts = Rc::new(RefCell::new(Vec::new()));
+/// let test = TestQueryClient::with_options(ClientOptions {
+///     on_query_error: Some(Rc::new({
+///         let toasts = toasts.clone();
+///         move |_key, meta: Option<&QueryMeta>, err| {
+///             let silent = meta.is_some_and(|meta| meta.get("toast").is_some_and(|v| v == "off"));
+///             if let Some(err) = err.downcast_ref::<String>().filter(|_| !silent) {
+///                 toasts.borrow_mut().push(err.clone());
+///             }
+///         }
+///     })),
+///     ..ClientOptions::test_defaults()
+/// });
+///
+/// create_scope_immediate(|cx| {
+///     provide_context(cx, test.client());
+///     use_query(cx, ("user", 1), || fetch_user(1));
+///     QueryBuilder::new(("user", 2), || fetch_user(2))
+///         .meta("toast", "off")
+///         .use_query(cx);
+///     test.reject(("user", 1), "Not found".to_string());
+///     test.reject(("user", 2), "Not found either".to_string());
+/// });
+/// assert_eq!(*toasts.borrow(), vec!["Not found".to_string()]);
+/// # }
+/// ```
+pub type QueryHandler = Rc<dyn Fn(&QueryKey, Option<&QueryMeta>, Rc<dyn Any>)>;
+/// A global handler for settled mutations, see
+/// [`ClientOptions::on_mutation_success`].
+pub type MutationHandler = Rc<dyn Fn(Option<&QueryKey>, Rc<dyn Any>)>;
+
 impl Default for ClientOptions {
     fn default() -> Self {
         Self {
             cache_expiration: Duration::from_secs(5 * 60),
+            stale_time: Duration::ZERO,
             retries: 3,
-            retry_fn: Rc::new(|retries| {
-                Duration::from_secs((1 ^ (2 * retries)).clamp(0, 30) as u64)
-            }),
+            background_retries: None,
+            retry_fn: RetryDelay::exponential(Duration::from_secs(1), Duration::from_secs(30)),
+            refetch_on_window_focus: false,
+            focus_manager: Rc::new(WindowFocus),
+            online_manager: Rc::new(NavigatorOnline),
+            logger: cfg!(debug_assertions).then(|| Rc::new(LogLogger) as Rc<dyn Logger>),
+            ssr: !cfg!(any(feature = "tokio", feature = "async-std"))
+                && (!cfg!(target_arch = "wasm32") || web_sys::window().is_none()),
+            max_bytes: None,
+            clock: Rc::new(SystemClock),
+            skip_delays: false,
+            on_query_success: None,
+            on_query_error: None,
+            on_mutation_success: None,
+            on_mutation_error: None,
+            max_concurrent_fetches: None,
         }
     }
 }
 
 impl ClientOptions {
+    /// Options for tests: no retries and no delays, so a failing fetch fails
+    /// right away and nothing waits on a real timer. Everything else is the
+    /// default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// let client = QueryClient::new(ClientOptions {
+    ///     stale_time: std::time::Duration::from_secs(60),
+    ///     ..ClientOptions::test_defaults()
+    /// });
+    /// ```
+    pub fn test_defaults() -> Self {
+        Self {
+            retries: 0,
+            retry_fn: Rc::new(|_| Duration::ZERO),
+            skip_delays: true,
+            ..Default::default()
+        }
+    }
+
     pub(crate) fn merge(&self, query_options: &QueryOptions) -> ClientOptions {
         Self {
             cache_expiration: query_options
                 .cache_expiration
                 .unwrap_or(self.cache_expiration),
+            stale_time: query_options.stale_time.unwrap_or(self.stale_time),
             retries: query_options.retries.unwrap_or(self.retries),
+            background_retries: query_options.background_retries.or(self.background_retries),
             retry_fn: query_options
                 .retry_fn
                 .clone()
                 .unwrap_or_else(|| self.retry_fn.clone()),
+            ..self.clone()
         }
     }
 }
 
+/// Constructors for common `retry_fn`s, for use in [`ClientOptions`] and
+/// [`QueryOptions`].
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::{ClientOptions, RetryDelay};
+/// # use std::time::Duration;
+/// let retry_fn = RetryDelay::exponential(Duration::from_secs(1), Duration::from_secs(30));
+/// let delays: Vec<_> = (0..7).map(|retry| retry_fn(retry).as_secs()).collect();
+/// assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+/// assert_eq!(retry_fn(u32::MAX), Duration::from_secs(30));
+///
+/// let retry_fn = RetryDelay::constant(Duration::from_millis(500));
+/// assert!((0..5).all(|retry| retry_fn(retry) == Duration::from_millis(500)));
+///
+/// let retry_fn = RetryDelay::none();
+/// assert_eq!(retry_fn(3), Duration::ZERO);
+///
+/// let options = ClientOptions {
+///     retry_fn: RetryDelay::constant(Duration::from_secs(2)),
+///     ..Default::default()
+/// };
+/// ```
+pub struct RetryDelay;
+
+impl RetryDelay {
+    /// Exponential backoff: `base * 2^retries`, but never more than `max`.
+    /// This is the default with a base of 1 second and a max of 30 seconds.
+    pub fn exponential(base: Duration, max: Duration) -> Rc<dyn Fn(u32) -> Duration> {
+        Rc::new(move |retries| {
+            2u32.checked_pow(retries)
+                .and_then(|factor| base.checked_mul(factor))
+                .map_or(max, |delay| delay.min(max))
+        })
+    }
+
+    /// Exponential backoff with full jitter: a random delay between zero and
+    /// what [`exponential`](RetryDelay::exponential) would wait. This spreads
+    /// out retries from many clients that failed at the same time, so they
+    /// don't all hit the backend again at once.
+    pub fn exponential_jitter(base: Duration, max: Duration) -> Rc<dyn Fn(u32) -> Duration> {
+        Self::exponential_jitter_with_rng(base, max, random)
+    }
+
+    /// Like [`exponential_jitter`](RetryDelay::exponential_jitter), but with a
+    /// custom source of randomness. `rng` must return a number in `0.0..1.0`.
+    /// Useful for deterministic tests.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::RetryDelay;
+    /// # use std::time::Duration;
+    /// let retry_fn = RetryDelay::exponential_jitter_with_rng(
+    ///     Duration::from_secs(1),
+    ///     Duration::from_secs(30),
+    ///     || 0.5,
+    /// );
+    /// let delays: Vec<_> = (0..6).map(|retry| retry_fn(retry).as_millis()).collect();
+    /// assert_eq!(delays, [500, 1000, 2000, 4000, 8000, 15000]);
+    /// ```
+    pub fn exponential_jitter_with_rng(
+        base: Duration,
+        max: Duration,
+        rng: impl Fn() -> f64 + 'static,
+    ) -> Rc<dyn Fn(u32) -> Duration> {
+        let exponential = Self::exponential(base, max);
+        Rc::new(move |retries| exponential(retries).mul_f64(rng().clamp(0.0, 1.0)))
+    }
+
+    /// The same delay between every retry.
+    pub fn constant(delay: Duration) -> Rc<dyn Fn(u32) -> Duration> {
+        Rc::new(move |_| delay)
+    }
+
+    /// Retry immediately.
+    pub fn none() -> Rc<dyn Fn(u32) -> Duration> {
+        Self::constant(Duration::ZERO)
+    }
+}
+
+/// A uniformly distributed random number in `0.0..1.0`. Falls back to `1.0`,
+/// i.e. no jitter, if no randomness is available.
+fn random() -> f64 {
+    let mut bytes = [0; 8];
+    match getrandom::getrandom(&mut bytes) {
+        Ok(()) => (u64::from_le_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64,
+        Err(_) => 1.0,
+    }
+}
+
 /// Query-specific options that override the global [`ClientOptions`].
 /// Any fields that are not set are defaulted to the [`QueryClient`]'s settings.
 ///
 /// # Options
 ///
-/// * `cache_expiration` - The time before a cached query result expires.
+/// * `cache_expiration` - The time before a cached query result expires,
+///   counted from when the last query using it unmounts.
+/// * `stale_time` - The time after which cached data is considered stale.
 /// * `retries` - The number of times to retry a query if it fails. Default: 3
+/// * `background_retries` - The number of times to retry a refetch of a query
+///   that already shows data. Default: `retries`
 /// * `retry_fn` - The function for the timeout between retries. Defaults to
-/// exponential delay starting with 1 second, but not going over 30 seconds.
+///   exponential delay starting with 1 second, but not going over 30 seconds.
+/// * `retry_delay_with_error` - Called with the number of retries and the
+///   type erased error of the failed attempt before `retry_fn`, for example
+///   to respect a `Retry-After` header. Returning `None` falls back to this
+///   query's `retry_fn`, or the client's if that isn't set either. Use
+///   [`QueryBuilder::retry_delay_with_error`](crate::query::QueryBuilder::retry_delay_with_error)
+///   for a typed version. Default: not set
+/// * `on_retry` - Called with the number of the retry (starting at 1) every
+///   time the query failed and is about to be retried. Useful for showing
+///   progress on long retry sequences.
+/// * `debounce` - Wait until the key hasn't changed for this long before
+///   fetching data for a new key. Useful for search-as-you-type. The first
+///   fetch when the query is mounted isn't delayed. Default: no debounce
+/// * `refetch_interval` - Refetch the query this long after the previous
+///   fetch settled, for as long as it's mounted and enabled. The interval
+///   counts from when the previous fetch completed, so a slow fetch never
+///   overlaps the next one. Default: no polling
+/// * `meta` - Arbitrary metadata for the query, like whether errors should
+///   show a toast. Passed to the global query handlers and the [`Logger`],
+///   shown in the devtools and available from [`QueryClient::query_meta`].
+///   Mounting a query with `meta` replaces the metadata of its key. Default:
+///   `None`
 ///
-#[derive(Default)]
+/// # Queries sharing a key
+///
+/// All queries mounted with the same key share one fetch, so when they were
+/// mounted with different options, every fetch for the key combines them:
+///
+/// * `stale_time` - The shortest, so the data is refetched as soon as any
+///   query considers it stale.
+/// * `cache_expiration`, `retries` and `background_retries` - The largest,
+///   so no query gets less than it asked for.
+/// * `retry_fn` and `retry_delay_with_error` - The ones of the query that was
+///   mounted first among those setting them.
+/// * `on_retry` - Every query's callback is called.
+/// * `debounce`, `refetch_interval` and `enabled` - Apply to each query on
+///   its own, since they decide when that query starts a fetch.
+///
+/// Unset options count as the client's value, see [`ClientOptions`]. The
+/// options are combined again before every retry, so a query mounted while a
+/// fetch is running counts too. Fetches for keys with no mounted queries,
+/// like prefetches, use their own options.
+///
+/// ```
+/// # #[cfg(feature = "test-util")] {
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, testing::TestQueryClient};
+/// # async fn fetch_user() -> Result<String, String> { unreachable!() }
+/// let test = TestQueryClient::new();
+/// create_scope_immediate(|cx| {
+///     provide_context(cx, test.client());
+///     let header = QueryBuilder::new("user", fetch_user).retries(0).use_query(cx);
+///     let profile = QueryBuilder::new("user", fetch_user).retries(2).use_query(cx);
+///
+///     // The fetch is retried twice, for the profile's sake
+///     test.reject("user", "Timeout".to_string());
+///     test.reject("user", "Timeout".to_string());
+///     assert!(!test.pending_fetches().is_empty());
+///     test.resolve("user", "Alice".to_string());
+///     assert!(header.data.get().is_ok() && profile.data.get().is_ok());
+/// });
+/// # }
+/// ```
+#[derive(Clone, Default)]
 pub struct QueryOptions {
-    /// The time before a cached query result expires. Default: 5 minutes
+    /// The time before a cached query result expires, counted from when the
+    /// last query using it unmounts. Default: 5 minutes
+    #[doc(alias = "gc_time")]
     pub cache_expiration: Option<Duration>,
+    /// The time after which cached data is considered stale. Default: 0
+    pub stale_time: Option<Duration>,
     /// The number of times to retry a query if it fails. Default: 3
     pub retries: Option<u32>,
+    /// The number of times to retry a refetch of a query that already shows
+    /// data. Default: `retries`
+    pub background_retries: Option<u32>,
     /// The function for the timeout between retries. Defaults to
     /// exponential delay starting with 1 second, but not going over 30 seconds.
     pub retry_fn: Option<Rc<dyn Fn(u32) -> Duration>>,
+    /// Called with the number of retries and the type erased error of the
+    /// failed attempt before `retry_fn`. Returning `None` falls back to
+    /// `retry_fn`. Default: not set
+    pub retry_delay_with_error: Option<ErasedRetryDelay>,
+    /// Called with the number of the retry (starting at 1) every time the
+    /// query failed and is about to be retried.
+    pub on_retry: Option<Rc<dyn Fn(u32)>>,
+    /// Wait until the key hasn't changed for this long before fetching data
+    /// for a new key. Default: no debounce
+    pub debounce: Option<Duration>,
+    /// Refetch the query this long after the previous fetch settled.
+    /// Default: no polling
+    pub refetch_interval: Option<Duration>,
+    /// Arbitrary metadata for the query. Default: `None`
+    pub meta: Option<QueryMeta>,
+}
+
+/// Metadata attached to a query, see [`QueryOptions::meta`].
+pub type QueryMeta = Rc<HashMap<String, String>>;
+
+/// Options for a mutation.
+///
+/// # Options
+///
+/// * `mutation_key` - An optional key for the mutation. Mutations are tracked
+///   by the [`QueryClient`] regardless, but the key makes it possible to look
+///   them up with [`QueryClient::mutations_with_key`].
+/// * `client` - The client to run the mutation with. Defaults to the
+///   [`QueryClient`] provided as a context. Use this if your app has multiple
+///   clients.
+/// * `keep_state` - Restore the state of the last mutation with the same
+///   `mutation_key` when the hook is created, so it survives the component
+///   being unmounted and mounted again. Default: `false`
+/// * `retries` - The number of times to retry the mutation if it fails.
+///   Mutations often aren't idempotent, so they aren't retried by default.
+///   Default: 0
+/// * `retry_fn` - The function for the timeout between retries. Defaults to
+///   the client's [`retry_fn`](ClientOptions::retry_fn)
+///
+/// # Lifecycle
+///
+/// By default, the `data` and `status` returned by the hook belong to the
+/// component and start over every time it mounts. With `keep_state`, they
+/// start out with the state of the most recent mutation that was started
+/// with the same `mutation_key` and is still tracked by the client. If that
+/// mutation is still running, the hook follows it until it settles or
+/// `mutate` is called again. Settled mutations are removed by
+/// [`QueryClient::collect_garbage`] once they're older than
+/// [`ClientOptions::cache_expiration`], after which the state is gone.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{*, mutation::use_mutation_with_options};
+/// # async fn submit(step: u32) -> Result<u32, String> { Ok(step) }
+/// let client = QueryClient::new(ClientOptions::default());
+/// // Offline, so the mutation is paused instead of sent
+/// client.set_online(false);
+/// let options = || MutationOptions {
+///     mutation_key: Some("wizard".as_keys()),
+///     keep_state: true,
+///     ..Default::default()
+/// };
+///
+/// create_scope_immediate(|cx| {
+///     provide_context(cx, client.clone());
+///     let mutation = use_mutation_with_options(cx, submit, |_, _| {}, options());
+///     (mutation.mutate)(1);
+/// });
+/// // Mounted again, the paused mutation is still there
+/// create_scope_immediate(|cx| {
+///     provide_context(cx, client.clone());
+///     let mutation = use_mutation_with_options(cx, submit, |_, _| {}, options());
+///     assert_eq!(*mutation.status.get(), Status::Paused);
+/// });
+/// ```
+#[derive(Default)]
+pub struct MutationOptions {
+    /// An optional key for the mutation. Mutations are tracked by the
+    /// [`QueryClient`] regardless, but the key makes it possible to look them
+    /// up with [`QueryClient::mutations_with_key`].
+    pub mutation_key: Option<QueryKey>,
+    /// The client to run the mutation with. Defaults to the [`QueryClient`]
+    /// provided as a context.
+    pub client: Option<Rc<QueryClient>>,
+    /// Restore the state of the last mutation with the same `mutation_key`
+    /// when the hook is created. Default: `false`
+    pub keep_state: bool,
+    /// The number of times to retry the mutation if it fails. Default: 0
+    pub retries: u32,
+    /// The function for the timeout between retries. Defaults to the
+    /// client's `retry_fn`
+    pub retry_fn: Option<Rc<dyn Fn(u32) -> Duration>>,
+}
+
+/// Options for [`QueryClient::set_query_data_with_options`].
+///
+/// # Options
+///
+/// * `preserve_timestamp` - Keep the creation time of the existing cache entry
+///   instead of resetting it, so the data still expires when the original
+///   data would have. Default: `false`
+///
+#[derive(Default, Clone, Copy)]
+pub struct SetDataOptions {
+    /// Keep the creation time of the existing cache entry instead of resetting
+    /// it, so the data still expires when the original data would have.
+    /// Default: `false`
+    pub preserve_timestamp: bool,
+}
+
+/// Something that happened to a query, delivered to subscribers of
+/// [`QueryClient::subscribe`]. Each event carries the hashed key of the
+/// query, which [`QueryClient::describe_key`] turns into a readable form and
+/// [`QueryClient::query_meta`] into the query's metadata.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueryEvent {
+    /// A query was mounted for a key that had no mounted query.
+    Added {
+        /// The key of the query
+        key: QueryKey,
+    },
+    /// The cached data of a query was replaced, by a fetch or manually.
+    Updated {
+        /// The key of the query
+        key: QueryKey,
+    },
+    /// A fetch started, including refetches and prefetches.
+    Fetching {
+        /// The key of the query
+        key: QueryKey,
+    },
+    /// A fetch settled after all retries.
+    Settled {
+        /// The key of the query
+        key: QueryKey,
+        /// Whether the fetch succeeded
+        success: bool,
+    },
+    /// A mounted query was invalidated and is about to be refetched.
+    Invalidated {
+        /// The key of the query
+        key: QueryKey,
+    },
+    /// Cached data was removed, because it was invalidated, removed
+    /// explicitly or expired.
+    Removed {
+        /// The key of the query
+        key: QueryKey,
+    },
+}
+
+/// A snapshot of the query cache, returned by [`QueryClient::cache_stats`].
+/// Useful to check whether your stale and cache times are tuned well.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of entries in the cache, including expired entries that
+    /// haven't been garbage collected yet.
+    pub entries: usize,
+    /// The number of cache lookups that found live data.
+    pub hits: u64,
+    /// The number of cache lookups that found no data or expired data.
+    pub misses: u64,
+    /// A rough estimate of the memory used by cached values. Unless a
+    /// [`SizeHint`] is registered for their type, only the values themselves
+    /// are counted, not any heap allocations they own, so a `Vec` or `String`
+    /// only contributes its stack size.
+    pub bytes_estimate: usize,
+}
+
+/// A snapshot of a single cache entry, returned by
+/// [`QueryClient::cache_entries`]. Useful for building cache inspectors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheEntryInfo {
+    /// The key of the cached query
+    pub key: QueryKey,
+    /// How old the data is. For hydrated data, this includes how old it
+    /// already was when it was hydrated.
+    pub age: Duration,
+    /// How long the entry is kept after the last query using it unmounts,
+    /// see [`ClientOptions::cache_expiration`]
+    pub lifetime: Duration,
+    /// Whether the data is older than the client's
+    /// [`stale_time`](ClientOptions::stale_time). Queries with their own
+    /// `stale_time` may disagree.
+    pub is_stale: bool,
+}
+
+/// Estimates how much memory a value uses, for
+/// [`ClientOptions::max_bytes`] and [`CacheStats::bytes_estimate`].
+///
+/// Cached data is type erased, so the client can only measure the value
+/// itself by default, not any heap memory it owns. Implement this for your
+/// query data types and register them with
+/// [`QueryClient::register_size_hint`] for a better estimate. It's
+/// implemented for `String`, `Vec`s of types that implement it and the
+/// primitive types.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::*;
+/// struct Avatar {
+///     png: Vec<u8>,
+/// }
+///
+/// impl SizeHint for Avatar {
+///     fn size_hint(&self) -> usize {
+///         std::mem::size_of::<Self>() + self.png.len()
+///     }
+/// }
+///
+/// let client = QueryClient::new(ClientOptions {
+///     max_bytes: Some(1024),
+///     ..Default::default()
+/// });
+/// client.register_size_hint::<Avatar>();
+///
+/// client.set_query_data(("avatar", 1), Avatar { png: vec![0; 600] });
+/// client.set_query_data(("avatar", 2), Avatar { png: vec![0; 600] });
+/// // The least recently used avatar was evicted to stay within the budget
+/// assert!(client.query_data::<_, Avatar>(("avatar", 1)).is_none());
+/// assert!(client.query_data::<_, Avatar>(("avatar", 2)).is_some());
+/// ```
+pub trait SizeHint {
+    /// The estimated size of `self` in bytes, including the heap memory it
+    /// owns.
+    fn size_hint(&self) -> usize;
+}
+
+impl SizeHint for String {
+    fn size_hint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.capacity()
+    }
+}
+
+impl<T: SizeHint> SizeHint for Vec<T> {
+    fn size_hint(&self) -> usize {
+        let unused = self.capacity() - self.len();
+        std::mem::size_of::<Self>()
+            + unused * std::mem::size_of::<T>()
+            + self.iter().map(SizeHint::size_hint).sum::<usize>()
+    }
+}
+
+macro_rules! impl_size_hint {
+    ($($ty:ty),*) => {
+        $(impl SizeHint for $ty {
+            fn size_hint(&self) -> usize {
+                std::mem::size_of::<Self>()
+            }
+        })*
+    };
 }
 
-type WeakFnvMap<T> = WeakValueHashMap<Vec<u64>, Weak<T>, FnvBuildHasher>;
+impl_size_hint!(
+    bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+type ErasedRetryDelay = Rc<dyn Fn(u32, &dyn Any) -> Option<Duration>>;
+type WeakFnvMap<T> = WeakValueHashMap<QueryKey, Weak<T>, FnvBuildHasher>;
 
 /// The query client for `sycamore-query`. This stores your default settings,
 /// the cache and all queries that need to be updated when a query is refetched
@@ -101,15 +755,115 @@ type WeakFnvMap<T> = WeakValueHashMap<Vec<u64>, Weak<T>, FnvBuildHasher>;
 /// }
 /// ```
 ///
-#[derive(Default)]
 pub struct QueryClient {
+    /// The client itself, so methods borrowing it can hand an owned client to
+    /// the futures they spawn
+    this: Weak<QueryClient>,
     pub(crate) default_options: ClientOptions,
     pub(crate) cache: RwLock<QueryCache>,
-    pub(crate) data_signals: RwLock<WeakFnvMap<DataSignal>>,
-    pub(crate) status_signals: RwLock<WeakFnvMap<Signal<Status>>>,
-    pub(crate) fetchers: RwLock<FnvHashMap<Vec<u64>, Fetcher>>,
+    pub(crate) state_signals: RwLock<WeakFnvMap<StateSignal>>,
+    /// The keys of `state_signals`, for prefix lookups. May still contain
+    /// queries that were dropped since the last garbage collection.
+    pub(crate) mounted_keys: RwLock<PrefixIndex>,
+    pub(crate) source_signals: RwLock<WeakFnvMap<RcSignal<DataSource>>>,
+    pub(crate) fetchers: RwLock<FnvHashMap<QueryKey, Fetcher>>,
+    /// The options of every mounted query, by key and in mount order
+    pub(crate) observer_options: RwLock<FnvHashMap<QueryKey, Vec<Rc<QueryOptions>>>>,
+    pub(crate) metas: RwLock<FnvHashMap<QueryKey, QueryMeta>>,
+    pub(crate) default_query_fns: RwLock<Vec<(QueryKey, crate::query::DefaultQueryFn)>>,
+    pub(crate) in_flight: RwLock<FnvHashMap<QueryKey, InFlight>>,
+    pub(crate) fetch_limit: FetchLimit,
+    pub(crate) mutation_cache: RwLock<MutationCache>,
+    pub(crate) paused_mutation_count: RcSignal<usize>,
+    pub(crate) cache_changes: RcSignal<u64>,
+    events: Rc<Listeners<QueryEvent>>,
+    pub(crate) offline: Cell<bool>,
+    pub(crate) resuming_mutations: Cell<bool>,
+    pending_writes: RefCell<PendingWrites>,
+    focus_subscription: RefCell<Option<Subscription>>,
+    online_subscription: RefCell<Option<Subscription>>,
+    #[cfg(feature = "readable-keys")]
+    pub(crate) key_reprs: RwLock<FnvHashMap<QueryKey, String>>,
+    pub(crate) data_types: RwLock<FnvHashMap<QueryKey, (std::any::TypeId, &'static str)>>,
+    #[cfg(feature = "test-util")]
+    pub(crate) test_env: std::cell::OnceCell<Rc<crate::testing::TestEnv>>,
+    #[cfg(feature = "test-util")]
+    pub(crate) fetch_log: RefCell<Option<Vec<Rc<RefCell<crate::testing::FetchRecord>>>>>,
+}
+
+/// Counts a running [`QueryClient::batch`] for as long as it's alive, so the
+/// count goes back down even if the batch panics.
+struct BatchScope<'a>(&'a QueryClient);
+
+impl<'a> BatchScope<'a> {
+    fn enter(client: &'a QueryClient) -> Self {
+        client.pending_writes.borrow_mut().depth += 1;
+        Self(client)
+    }
+}
+
+impl Drop for BatchScope<'_> {
+    fn drop(&mut self) {
+        let mut pending = self.0.pending_writes.borrow_mut();
+        pending.depth -= 1;
+        // Applying the deferred writes could panic again while unwinding, so
+        // they're dropped. Otherwise they'd leak into the next batch.
+        if pending.depth == 0 && std::thread::panicking() {
+            *pending = PendingWrites::default();
+        }
+    }
+}
+
+/// Signal writes deferred until the end of a [`QueryClient::batch`].
+#[derive(Default)]
+struct PendingWrites {
+    /// How many batches are currently running, for nested calls
+    depth: usize,
+    /// The last data and status written to each key, in the order they were
+    /// first written
+    data: Vec<(QueryKey, DataSource, ErasedData, Option<Status>)>,
+    cache_changed: bool,
+    /// Invalidated prefixes whose mounted queries still need to be refetched
+    invalidated: Vec<QueryKey>,
+    /// Invalidated keys nothing uses, refetched if they have a default query
+    /// function
+    unused: Vec<QueryKey>,
+}
+
+/// The error returned when query data doesn't have the requested type. This
+/// happens when two queries with different data types share the same key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypeMismatch {
+    /// The key of the query, human readable if the `readable-keys` feature is
+    /// enabled.
+    pub key: String,
+    /// The requested type.
+    pub expected: &'static str,
+    /// The type the query was first used with, if it's known.
+    pub found: Option<&'static str>,
+}
+
+impl Display for TypeMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Query {} was used with type `{}`",
+            self.key, self.expected
+        )?;
+        match self.found {
+            Some(found) => write!(f, ", but its data has type `{found}`.")?,
+            None => write!(f, ", but its data has a different type.")?,
+        }
+        write!(
+            f,
+            " Two queries with different types share the same key. \
+            Make their keys distinct, for example by starting each key with a unique name."
+        )
+    }
 }
 
+impl Error for TypeMismatch {}
+
 impl QueryClient {
     /// Creates a new QueryClient.
     ///
@@ -123,16 +877,99 @@ impl QueryClient {
     /// let client = QueryClient::new(ClientOptions::default());
     /// ```
     pub fn new(default_options: ClientOptions) -> Rc<Self> {
-        Rc::new(Self {
+        let clock = CacheClock(default_options.clock.clone());
+        let client = Rc::new_cyclic(|this| Self {
+            this: this.clone(),
+            offline: Cell::new(!default_options.ssr && !default_options.online_manager.is_online()),
+            cache: RwLock::new(QueryCache::with_clock(clock.clone())),
+            mutation_cache: RwLock::new(MutationCache::with_clock(clock)),
+            fetch_limit: FetchLimit::new(default_options.max_concurrent_fetches),
             default_options,
-            ..QueryClient::default()
-        })
+            state_signals: Default::default(),
+            mounted_keys: Default::default(),
+            source_signals: Default::default(),
+            fetchers: Default::default(),
+            observer_options: Default::default(),
+            metas: Default::default(),
+            default_query_fns: Default::default(),
+            in_flight: Default::default(),
+            paused_mutation_count: Default::default(),
+            cache_changes: Default::default(),
+            events: Default::default(),
+            resuming_mutations: Default::default(),
+            pending_writes: Default::default(),
+            focus_subscription: Default::default(),
+            online_subscription: Default::default(),
+            #[cfg(feature = "readable-keys")]
+            key_reprs: Default::default(),
+            data_types: Default::default(),
+            #[cfg(feature = "test-util")]
+            test_env: Default::default(),
+            #[cfg(feature = "test-util")]
+            fetch_log: Default::default(),
+        });
+        if client.default_options.ssr {
+            return client;
+        }
+        let weak = Rc::downgrade(&client);
+        let subscription =
+            client
+                .default_options
+                .online_manager
+                .subscribe(Rc::new(move |online| {
+                    if let Some(client) = weak.upgrade() {
+                        client.set_online(online);
+                    }
+                }));
+        *client.online_subscription.borrow_mut() = Some(subscription);
+        if client.default_options.refetch_on_window_focus {
+            let weak = Rc::downgrade(&client);
+            let subscription =
+                client
+                    .default_options
+                    .focus_manager
+                    .subscribe(Rc::new(move |focused| {
+                        if let Some(client) = weak.upgrade().filter(|_| focused) {
+                            client.refetch_stale_queries();
+                        }
+                    }));
+            *client.focus_subscription.borrow_mut() = Some(subscription);
+        }
+        client
+    }
+
+    /// Refetch all mounted queries whose data is stale according to the
+    /// client's `stale_time`. They keep their data while refetching.
+    fn refetch_stale_queries(&self) {
+        let keys: Vec<_> = self.state_signals.read().unwrap().keys().cloned().collect();
+        for key in keys {
+            let stale = self
+                .cache
+                .read()
+                .unwrap()
+                .is_stale(&key, self.default_options.stale_time);
+            if stale {
+                self.refetch_query(&key, &QueryOptions::default(), true);
+            }
+        }
     }
 
     /// Invalidate all queries whose keys start with any of the keys passed in.
     /// For example, passing a top level query ID will invalidate all queries
     /// with that top level ID, regardless of their arguments.
-    /// For passing multiple keys with tuple types, see [`keys!`](crate::keys).
+    /// This takes a single key or a list of keys, see [`IntoKeys`]. For passing
+    /// multiple keys with tuple types, see [`keys!`](crate::keys).
+    ///
+    /// Cached data no mounted query uses is removed, unless there's a default
+    /// query function for it (see
+    /// [`set_default_query_fn`](QueryClient::set_default_query_fn)), which
+    /// refetches it in the background.
+    ///
+    /// Invalidated queries keep their current data while they refetch, so
+    /// nothing flashes back to [`QueryData::Loading`]. Their status is
+    /// [`Status::Fetching`] until the new data arrives. Queries split into
+    /// multiple keys (like pages of a list) each refetch independently and
+    /// keep showing the previous data until their own fetch settles.
     ///
     /// # Example
     ///
@@ -142,34 +979,137 @@ impl QueryClient {
     /// // This will invalidate all queries whose keys start with `"hello"`,
     /// // or where the first key is `"user"` and the first argument `3`
     /// client.invalidate_queries(keys!["hello", ("user", 3)]);
+    /// // A single key doesn't need the macro
+    /// client.invalidate_queries("hello");
+    ///
+    /// // A borrowed client is enough
+    /// fn rename_user(client: &QueryClient, id: u32) {
+    ///     client.invalidate_queries(("user", id));
+    /// }
+    /// rename_user(&client, 3);
     /// ```
     ///
-    pub fn invalidate_queries(self: Rc<Self>, queries: Vec<Vec<u64>>) {
-        let queries = queries
-            .iter()
-            .map(|query| query.as_slice())
-            .collect::<Vec<_>>();
-        self.cache.write().unwrap().invalidate_keys(&queries);
-        log::info!(
-            "Invalidating queries: {queries:?}. Queries in cache: {:?}",
-            self.data_signals.read().unwrap().keys().collect::<Vec<_>>()
-        );
-        for query in self
-            .data_signals
-            .read()
-            .unwrap()
-            .keys()
-            .filter(|k| queries.iter().any(|key| k.starts_with(key)))
-        {
-            log::info!("Updating query {query:?}");
-            if let Some((data, status, fetcher)) = self.find_query(query, false) {
-                log::info!("Query present. Running fetch.");
-                self.clone()
-                    .run_query(query, data, status, fetcher, &QueryOptions::default());
+    pub fn invalidate_queries(&self, queries: impl IntoKeys) {
+        let queries = queries.into_keys();
+        let removed = self.cache.write().unwrap().invalidate_keys(&queries);
+        // Cached data nothing uses anymore is only refetched if there's a
+        // default query function for it
+        let unused: Vec<_> = {
+            let mounted = self.state_signals.read().unwrap();
+            removed
+                .iter()
+                .filter(|key| !mounted.contains_key(key))
+                .cloned()
+                .collect()
+        };
+        self.emit_removed(removed);
+        let batched = {
+            let mut pending = self.pending_writes.borrow_mut();
+            if pending.depth > 0 {
+                pending.invalidated.extend(queries.iter().cloned());
+                pending.unused.extend(unused.iter().cloned());
+                true
+            } else {
+                false
+            }
+        };
+        if !batched {
+            self.refetch_invalidated(&queries, unused);
+        }
+        self.cache_changed();
+    }
+
+    /// Refetch the mounted queries under `prefixes` after they were
+    /// invalidated, and the `unused` cached keys that have a default query
+    /// function.
+    fn refetch_invalidated(&self, prefixes: &[QueryKey], unused: Vec<QueryKey>) {
+        for key in unused {
+            if let Some(fetcher) = self.default_fetcher(&key) {
+                self.prefetch_erased(key, fetcher);
+            }
+        }
+        let mounted = self.mounted_matching(prefixes);
+        for query in &mounted {
+            if let Some((state, fetcher)) = self.find_query(query, false) {
+                self.log(|logger| logger.invalidated(&self.key_repr(query)));
+                self.emit(|| QueryEvent::Invalidated { key: query.clone() });
+                #[cfg(feature = "tracing")]
+                crate::trace::invalidated(&self.key_repr(query));
+                self.run_query(query, state, fetcher, &QueryOptions::default());
+            }
+        }
+    }
+
+    /// Like [`invalidate_queries`](QueryClient::invalidate_queries), but also
+    /// returns a future that resolves once all the refetches it triggered
+    /// have settled, successfully or not. The queries are invalidated right
+    /// away, not when the future is first polled.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore::{prelude::*, futures::spawn_local_scoped};
+    /// # use sycamore_query::*;
+    /// # use std::rc::Rc;
+    /// # #[component]
+    /// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+    /// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+    /// # fn go_back() {}
+    /// let client = use_context::<Rc<QueryClient>>(cx).clone();
+    /// let save = move |_| {
+    ///     let refetched = client.invalidate_queries_async("todos");
+    ///     spawn_local_scoped(cx, async move {
+    ///         refetched.await;
+    ///         // Only navigate once the list shows the new data
+    ///         go_back();
+    ///     });
+    /// };
+    /// view! { cx, button(on:click=save) { "Save" } }
+    /// # }
+    /// ```
+    pub fn invalidate_queries_async(&self, queries: impl IntoKeys) -> impl Future<Output = ()> {
+        let queries = queries.into_keys();
+        self.invalidate_queries(queries.clone());
+        let pending = self.in_flight_matching(&queries);
+        async move {
+            for in_flight in pending {
+                in_flight.await;
             }
         }
     }
 
+    /// Invalidate every query, for when something all of them depend on
+    /// changed, like the user's locale or auth token. The same as
+    /// [`invalidate_queries`](QueryClient::invalidate_queries) with a prefix
+    /// matching every key, so there's no need to list the top level keys.
+    ///
+    /// All mounted queries are refetched, including ones whose last fetch
+    /// failed, which start over with their full number of retries.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, testing::TestQueryClient, *};
+    /// # async fn fetch(key: &str) -> Result<String, String> { unreachable!() }
+    /// let test = TestQueryClient::new();
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, test.client());
+    ///     let _ = use_query(cx, "user", || fetch("user"));
+    ///     let _ = use_query(cx, ("todos", 1), || fetch("todos"));
+    ///     test.resolve("user", "Alice".to_string());
+    ///     test.reject(("todos", 1), "Unauthorized".to_string());
+    ///
+    ///     test.client().invalidate_all();
+    ///     assert_eq!(test.pending_fetches().len(), 2);
+    /// });
+    /// # }
+    /// ```
+    pub fn invalidate_all(&self) {
+        self.invalidate_queries(QueryKey::default());
+    }
+
     /// Collect garbage from the client cache
     /// Call this whenever a lot of queries have been removed (i.e. on going to
     /// a different page) to keep memory usage low.
@@ -177,35 +1117,1487 @@ impl QueryClient {
     /// cache expiration time.
     ///
     /// This will iterate through the entire cache sequentially, so don't use
-    /// on every frame.
+    /// on every frame. See [`use_collect_garbage_on`] to collect garbage
+    /// automatically on navigation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, testing::TestQueryClient};
+    /// # async fn fetch_user() -> Result<String, String> { unreachable!() }
+    /// let test = TestQueryClient::new();
+    /// let client = test.client();
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, client.clone());
+    ///     let _ = QueryBuilder::new("user", fetch_user)
+    ///         .meta("page", "profile")
+    ///         .use_query(cx);
+    ///     test.resolve("user", "Alice".to_string());
+    ///     client.invalidate_queries("user");
+    ///     assert_eq!(test.pending_fetches().len(), 1);
+    ///     test.resolve("user", "Alicia".to_string());
+    /// });
+    ///
+    /// // The query's signals went away with its scope, so there's nothing
+    /// // left to refetch
+    /// client.invalidate_queries("user");
+    /// assert!(test.pending_fetches().is_empty());
+    /// // Its fetcher and metadata go with the garbage
+    /// assert!(client.query_meta("user").is_some());
+    /// client.collect_garbage();
+    /// assert!(client.query_meta("user").is_none());
+    /// # }
+    /// ```
     pub fn collect_garbage(&self) {
-        self.cache.write().unwrap().collect_garbage();
+        crate::reset_key_collision_check();
+        let removed = self.cache.write().unwrap().collect_garbage();
+        self.emit_removed(removed);
+        self.mutation_cache
+            .write()
+            .unwrap()
+            .collect_garbage(self.default_options.cache_expiration);
         // Queries get collected automatically, make sure to also collect fetchers
-        let queries = self.status_signals.read().unwrap();
+        let queries = self.state_signals.read().unwrap();
         self.fetchers
             .write()
             .unwrap()
             .retain(|k, _| queries.contains_key(k));
+        self.metas
+            .write()
+            .unwrap()
+            .retain(|k, _| queries.contains_key(k));
+        #[cfg(feature = "readable-keys")]
+        self.key_reprs
+            .write()
+            .unwrap()
+            .retain(|k, _| queries.contains_key(k));
+        {
+            let cache = self.cache.read().unwrap();
+            self.data_types
+                .write()
+                .unwrap()
+                .retain(|k, _| queries.contains_key(k) || cache.contains_key(k));
+        }
+        self.mounted_keys
+            .write()
+            .unwrap()
+            .retain(|k| queries.contains_key(k));
+        drop(queries);
+        self.cache_changed();
     }
 
-    /// Fetch query data from the cache if it exists. If it doesn't or the data
-    /// is expired, this will return `None`.
-    pub fn query_data<K: AsKeys, T: 'static>(&self, key: K) -> Option<Rc<T>> {
-        let data = self.cache.read().unwrap().get(&key.as_keys())?;
-        Some(data.clone().downcast().unwrap())
+    /// Remove the cached data of all queries whose keys start with any of the
+    /// keys passed in, without refetching. Mounted queries keep showing their
+    /// current data until they're refetched or mounted again.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # let client = QueryClient::new(ClientOptions::default());
+    /// client.set_query_data(("user", 1), "Alice".to_string());
+    /// client.remove_queries("user");
+    /// assert!(client.query_data::<_, String>(("user", 1)).is_none());
+    /// ```
+    pub fn remove_queries(&self, queries: impl IntoKeys) {
+        let queries = queries.into_keys();
+        let removed = self.cache.write().unwrap().invalidate_keys(&queries);
+        self.emit_removed(removed);
+        self.cache_changed();
     }
 
-    /// Override the query data in the cache for a given key. This will update
-    /// all queries with the same key automatically to reflect the new data.
-    pub fn set_query_data<K: AsKeys, T: 'static>(&self, key: K, value: T) {
+    /// Remove the cached data of the query with exactly `key`, without
+    /// refetching. Unlike [`remove_queries`](QueryClient::remove_queries),
+    /// queries whose keys only start with `key` are left alone, and mounted
+    /// queries with the key go back to [`QueryData::Loading`] and
+    /// [`Status::Idle`] right away, so they can show an empty state. They're
+    /// fetched again when they're invalidated, refetched or mounted again.
+    /// For a key nothing has mounted, the fetcher is dropped as well.
+    ///
+    /// Useful when the server reports that an entity was deleted, for
+    /// example from a mutation's `on_success`. A fetch for the key that's
+    /// still running writes its result when it settles.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, testing::TestQueryClient, *};
+    /// # async fn fetch_user() -> Result<String, String> { unreachable!() }
+    /// let test = TestQueryClient::new();
+    /// let client = test.client();
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, client.clone());
+    ///     let Query { data, status, .. } = use_query(cx, ("user", 1), fetch_user);
+    ///     test.resolve(("user", 1), "Alice".to_string());
+    ///     client.set_query_data(("user", 1, "posts"), vec!["Hello".to_string()]);
+    ///
+    ///     // The server says the user is gone
+    ///     client.remove_query(("user", 1));
+    ///     assert_eq!(*data.get(), QueryData::Loading);
+    ///     assert_eq!(*status.get(), Status::Idle);
+    ///     assert!(test.pending_fetches().is_empty());
+    ///     assert!(client.query_data::<_, String>(("user", 1)).is_none());
+    ///     assert!(client
+    ///         .query_data::<_, Vec<String>>(("user", 1, "posts"))
+    ///         .is_some());
+    /// });
+    /// # }
+    /// ```
+    pub fn remove_query(&self, key: impl AsKeys) {
         let key = key.as_keys();
-        let value = Rc::new(value);
-        if let Some(data) = self.data_signals.read().unwrap().get(&key) {
-            data.set(QueryData::Ok(value.clone()))
+        let removed = self.cache.write().unwrap().remove(&key);
+        if removed {
+            self.emit_removed(vec![key.clone()]);
         }
-        self.cache
-            .write()
-            .unwrap()
-            .insert(key, Rc::new(value), &self.default_options);
+        if self.state_signals.read().unwrap().contains_key(&key) {
+            self.write_data(
+                key,
+                DataSource::None,
+                QueryData::Loading,
+                Some(Status::Idle),
+            );
+        } else {
+            self.fetchers.write().unwrap().remove(&key);
+        }
+        self.cache_changed();
     }
+
+    /// Remove all cached query data, without refetching. Like
+    /// [`remove_queries`](QueryClient::remove_queries) for every key.
+    pub fn clear(&self) {
+        crate::reset_key_collision_check();
+        let removed = self.cache.write().unwrap().clear();
+        self.emit_removed(removed);
+        self.cache_changed();
+    }
+
+    /// A signal that's updated every time queries are added to or removed
+    /// from the cache, or their cached data changes. Useful for tools that
+    /// display the cache, like the `QueryDevtools` component.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # let client = QueryClient::new(ClientOptions::default());
+    /// let changes = *client.cache_changes().get();
+    /// client.set_query_data("hello", 1u64);
+    /// assert_ne!(*client.cache_changes().get(), changes);
+    /// ```
+    pub fn cache_changes(&self) -> &ReadSignal<u64> {
+        &self.cache_changes
+    }
+
+    /// Estimate the size of cached data of type `T` with its [`SizeHint`]
+    /// implementation. Applies to data cached from now on.
+    pub fn register_size_hint<T: SizeHint + 'static>(&self) {
+        self.cache.write().unwrap().register_size_hint(
+            std::any::TypeId::of::<T>(),
+            Rc::new(|value| {
+                value
+                    .downcast_ref::<T>()
+                    .map_or_else(|| std::mem::size_of_val(value), T::size_hint)
+            }),
+        );
+    }
+
+    /// Wait for `duration`, or not at all if
+    /// [`skip_delays`](ClientOptions::skip_delays) is set.
+    pub(crate) fn delay(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        if self.default_options.skip_delays {
+            return Box::pin(std::future::ready(()));
+        }
+        timer::delay(duration)
+    }
+
+    /// Whether queries with a
+    /// [`refetch_interval`](crate::QueryOptions::refetch_interval) poll.
+    /// They don't while rendering on the server or with
+    /// [`skip_delays`](ClientOptions::skip_delays), except under a
+    /// [`TestQueryClient`](crate::testing::TestQueryClient), where they follow
+    /// the client's clock.
+    pub(crate) fn polls(&self) -> bool {
+        #[cfg(feature = "test-util")]
+        if self.test_env.get().is_some() {
+            return true;
+        }
+        !self.default_options.ssr && !self.default_options.skip_delays
+    }
+
+    /// Wait for `duration` between the fetches of a
+    /// [`refetch_interval`](crate::QueryOptions::refetch_interval). Under a
+    /// [`TestQueryClient`](crate::testing::TestQueryClient), this waits for
+    /// the client's [`Clock`] instead of a timer, so tests can move it forward
+    /// with a `ManualClock`.
+    pub(crate) fn poll_delay(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        #[cfg(feature = "test-util")]
+        if self.test_env.get().is_some() {
+            let clock = self.default_options.clock.clone();
+            let deadline = clock.now() + duration;
+            return Box::pin(std::future::poll_fn(move |_| {
+                if clock.now() >= deadline {
+                    std::task::Poll::Ready(())
+                } else {
+                    std::task::Poll::Pending
+                }
+            }));
+        }
+        timer::delay(duration)
+    }
+
+    /// Pass the result of a fetch to the [`Logger`] and the global query
+    /// handlers.
+    pub(crate) fn query_settled(&self, key: &QueryKey, res: &Result<Rc<dyn Any>, Rc<dyn Any>>) {
+        let options = &self.default_options;
+        let meta = self.metas.read().unwrap().get(key).cloned();
+        self.log(|logger| {
+            logger.fetch_settled_with_meta(&self.key_repr(key), res.is_ok(), meta.as_ref())
+        });
+        // There's no data to pass on
+        if crate::query::is_empty_stream(res) {
+            return;
+        }
+        let (handler, value) = match res {
+            Ok(data) => (&options.on_query_success, data),
+            Err(err) => (&options.on_query_error, err),
+        };
+        if let Some(f) = handler {
+            f(key, meta.as_ref(), value.clone());
+        }
+    }
+
+    /// Call the global mutation handlers with the result of a mutation.
+    pub(crate) fn mutation_settled(
+        &self,
+        key: Option<&QueryKey>,
+        res: QueryData<Rc<dyn Any>, Rc<dyn Any>>,
+    ) {
+        let options = &self.default_options;
+        let (handler, value) = match res {
+            QueryData::Ok(data) => (&options.on_mutation_success, data),
+            QueryData::Err(err) => (&options.on_mutation_error, err),
+            QueryData::Loading | QueryData::Idle => return,
+        };
+        if let Some(f) = handler {
+            f(key, value);
+        }
+    }
+
+    /// An owned handle to the client, for futures that outlive the borrow of
+    /// `self`. Clients only exist behind the `Rc` from
+    /// [`new`](QueryClient::new), which also sets up the handle.
+    pub(crate) fn rc(&self) -> Rc<Self> {
+        self.this
+            .upgrade()
+            .expect("the client is always owned by the `Rc` from `QueryClient::new`")
+    }
+
+    /// Run `future` in the background. Outside the browser it's spawned on
+    /// tokio's `LocalSet` when the `tokio` feature is enabled. Under a
+    /// [`TestQueryClient`](crate::testing::TestQueryClient), it's queued
+    /// until the test flushes instead.
+    pub(crate) fn spawn(&self, future: impl Future<Output = ()> + 'static) {
+        #[cfg(feature = "test-util")]
+        if let Some(env) = self.test_env.get() {
+            env.spawn(future);
+            return;
+        }
+        #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+        tokio::task::spawn_local(future);
+        #[cfg(not(all(feature = "tokio", not(target_arch = "wasm32"))))]
+        sycamore::futures::spawn_local(future);
+    }
+
+    /// Call `fetcher` for `key`. Under a
+    /// [`TestQueryClient`](crate::testing::TestQueryClient), the fetcher isn't
+    /// called and the fetch waits for the test to resolve it instead.
+    pub(crate) fn call_fetcher(&self, key: &QueryKey, fetcher: &Fetcher) -> FetchFuture {
+        #[cfg(feature = "test-util")]
+        if let Some(env) = self.test_env.get() {
+            return env.pending_fetch(key.clone());
+        }
+        #[cfg(not(feature = "test-util"))]
+        let _ = key;
+        fetcher()
+    }
+
+    /// Notify [`cache_changes`](QueryClient::cache_changes) subscribers. Must
+    /// not be called while holding a lock they might need.
+    pub(crate) fn cache_changed(&self) {
+        {
+            let mut pending = self.pending_writes.borrow_mut();
+            if pending.depth > 0 {
+                pending.cache_changed = true;
+                return;
+            }
+        }
+        let evicted = self.cache.write().unwrap().take_evicted();
+        self.emit_removed(evicted);
+        self.cache_changes
+            .set(self.cache_changes.get_untracked().wrapping_add(1));
+    }
+
+    /// Call `callback` with every [`QueryEvent`] until the returned
+    /// [`Subscription`] is dropped. This is meant for devtools and logging
+    /// integrations.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # use std::{cell::RefCell, rc::Rc};
+    /// # let client = QueryClient::new(ClientOptions::default());
+    /// let events = Rc::new(RefCell::new(Vec::new()));
+    /// let subscription = client.subscribe({
+    ///     let events = events.clone();
+    ///     move |event| events.borrow_mut().push(event)
+    /// });
+    ///
+    /// client.set_query_data("hello", 1u64);
+    /// client.remove_queries("hello");
+    /// let key = "hello".as_keys();
+    /// assert_eq!(
+    ///     *events.borrow(),
+    ///     vec![
+    ///         QueryEvent::Updated { key: key.clone() },
+    ///         QueryEvent::Removed { key },
+    ///     ]
+    /// );
+    ///
+    /// drop(subscription);
+    /// client.set_query_data("hello", 2u64);
+    /// assert_eq!(events.borrow().len(), 2);
+    /// ```
+    pub fn subscribe(&self, callback: impl Fn(QueryEvent) + 'static) -> Subscription {
+        self.events.subscribe(Rc::new(callback))
+    }
+
+    /// A readable form of `key` for display, like `("user", 3)`, if the
+    /// `readable-keys` feature is enabled and the key was used by a query.
+    /// Otherwise, the hashed key.
+    pub fn describe_key(&self, key: &QueryKey) -> String {
+        self.key_repr(key)
+    }
+
+    /// The metadata of the query with `key`, see [`QueryOptions::meta`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, *};
+    /// # async fn fetch_user(id: u32) -> Result<String, String> { Ok(id.to_string()) }
+    /// let client = QueryClient::new(ClientOptions {
+    ///     # ssr: true,
+    ///     ..Default::default()
+    /// });
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, client.clone());
+    ///     QueryBuilder::new(("user", 1), || fetch_user(1))
+    ///         .meta("label", "profile")
+    ///         .use_query(cx);
+    /// });
+    /// let meta = client.query_meta(("user", 1)).unwrap();
+    /// assert_eq!(meta["label"], "profile");
+    /// ```
+    pub fn query_meta(&self, key: impl AsKeys) -> Option<QueryMeta> {
+        self.metas.read().unwrap().get(&key.as_keys()).cloned()
+    }
+
+    /// Replace the metadata of `key`.
+    pub(crate) fn set_query_meta(&self, key: &QueryKey, meta: QueryMeta) {
+        self.metas.write().unwrap().insert(key.clone(), meta);
+    }
+
+    /// Send an event to subscribers. The event is only created if there are
+    /// any.
+    pub(crate) fn emit(&self, event: impl FnOnce() -> QueryEvent) {
+        if !self.events.is_empty() {
+            self.events.notify(event());
+        }
+    }
+
+    /// The keys of all mounted queries starting with any of `prefixes`.
+    pub(crate) fn mounted_matching(&self, prefixes: &[QueryKey]) -> Vec<QueryKey> {
+        let mut keys = self.mounted_keys.read().unwrap().matching_any(prefixes);
+        let state_signals = self.state_signals.read().unwrap();
+        keys.retain(|key| state_signals.contains_key(key));
+        keys
+    }
+
+    fn emit_removed(&self, keys: Vec<QueryKey>) {
+        for key in keys {
+            self.emit(|| QueryEvent::Removed { key });
+        }
+    }
+
+    /// The keys of all mounted or cached queries.
+    #[cfg(feature = "devtools")]
+    pub(crate) fn query_keys(&self) -> Vec<QueryKey> {
+        let mut keys: Vec<_> = self.state_signals.read().unwrap().keys().cloned().collect();
+        let cache = self.cache.read().unwrap();
+        keys.extend(
+            cache
+                .keys()
+                .filter(|key| !keys.contains(key))
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+        keys
+    }
+
+    /// Statistics about the query cache. Hits and misses are counted since the
+    /// client was created or since the last call to
+    /// [`reset_stats`](QueryClient::reset_stats).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::{QueryClient, CacheStats};
+    /// let client = QueryClient::new(Default::default());
+    /// client.set_query_data("hello", 1u64);
+    /// assert!(client.query_data::<_, u64>("hello").is_some());
+    /// assert!(client.query_data::<_, u64>("world").is_none());
+    ///
+    /// let stats = client.cache_stats();
+    /// assert_eq!(stats.entries, 1);
+    /// assert_eq!((stats.hits, stats.misses), (1, 1));
+    /// assert_eq!(stats.bytes_estimate, 8);
+    ///
+    /// client.reset_stats();
+    /// assert_eq!(client.cache_stats().hits, 0);
+    /// ```
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.read().unwrap().stats()
+    }
+
+    /// A snapshot of every entry in the query cache, in no particular order.
+    /// Expired entries that haven't been garbage collected yet are left out.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # use std::time::Duration;
+    /// let client = QueryClient::new(ClientOptions {
+    ///     stale_time: Duration::from_secs(60),
+    ///     ..Default::default()
+    /// });
+    /// client.hydrate_query_data("fresh", 1u32, Duration::from_secs(10));
+    /// client.hydrate_query_data("stale", 2u32, Duration::from_secs(90));
+    ///
+    /// let mut entries = client.cache_entries();
+    /// entries.sort_by_key(|entry| entry.age);
+    /// assert_eq!(entries.len(), 2);
+    /// assert_eq!(entries[0].key, "fresh".as_keys());
+    /// assert!(!entries[0].is_stale);
+    /// assert!(entries[1].is_stale);
+    /// assert_eq!(entries[1].lifetime, Duration::from_secs(5 * 60));
+    /// ```
+    pub fn cache_entries(&self) -> Vec<CacheEntryInfo> {
+        self.cache
+            .read()
+            .unwrap()
+            .entries(self.default_options.stale_time)
+    }
+
+    /// The number of mounted queries using `key`. Cached data only starts
+    /// expiring once this drops to zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, testing::ManualClock, *};
+    /// # use std::{rc::Rc, time::Duration};
+    /// # async fn fetch_user() -> Result<String, String> { Ok(String::new()) }
+    /// let clock = ManualClock::default();
+    /// let client = QueryClient::new(ClientOptions {
+    ///     clock: Rc::new(clock.clone()),
+    ///     ..Default::default()
+    /// });
+    /// client.set_query_data("user", "Alice".to_string());
+    ///
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, client.clone());
+    ///     # let _ =
+    ///     QueryBuilder::new("user", fetch_user)
+    ///         # .enabled(|| false)
+    ///         .use_query(cx);
+    ///     assert_eq!(client.observer_count("user"), 1);
+    ///     // Data that's in use doesn't expire
+    ///     clock.advance(Duration::from_secs(10 * 60));
+    ///     client.collect_garbage();
+    ///     assert!(client.query_data::<_, String>("user").is_some());
+    /// });
+    ///
+    /// // Now that the query is unmounted, the data expires 5 minutes from now
+    /// assert_eq!(client.observer_count("user"), 0);
+    /// clock.advance(Duration::from_secs(4 * 60));
+    /// assert!(client.query_data::<_, String>("user").is_some());
+    /// clock.advance(Duration::from_secs(2 * 60));
+    /// assert!(client.query_data::<_, String>("user").is_none());
+    /// # }
+    /// ```
+    pub fn observer_count(&self, key: impl AsKeys) -> usize {
+        self.cache.read().unwrap().observer_count(&key.as_keys())
+    }
+
+    /// Reset the hit and miss counters returned by
+    /// [`cache_stats`](QueryClient::cache_stats).
+    pub fn reset_stats(&self) {
+        self.cache.read().unwrap().reset_stats();
+    }
+
+    /// Whether the query with exactly `key` is fetching right now. The status
+    /// is read without tracking it, so this is meant for event handlers and
+    /// other code outside the reactive graph, like not submitting a form
+    /// while the data it's based on is being refreshed. Use the `status`
+    /// signal of the [`Query`](crate::query::Query) to react to changes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # let client = QueryClient::new(ClientOptions::default());
+    /// let submit = move || {
+    ///     if client.is_fetching(("user", 1)) {
+    ///         return false;
+    ///     }
+    ///     // Submit the form
+    ///     true
+    /// };
+    /// assert!(submit());
+    /// ```
+    pub fn is_fetching(&self, key: impl AsKeys) -> bool {
+        self.state_signals
+            .read()
+            .unwrap()
+            .get(&key.as_keys())
+            .is_some_and(|state| state.get_untracked().1 == Status::Fetching)
+    }
+
+    /// Fetch query data from the cache if it exists. If it doesn't, the data
+    /// is expired or it isn't a `T`, this will return `None`. Use
+    /// [`try_query_data`](QueryClient::try_query_data) to tell a wrong type
+    /// apart from missing data.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # let client = QueryClient::new(ClientOptions::default());
+    /// client.set_query_data("count", 3u32);
+    /// assert_eq!(client.query_data::<_, u32>("count").as_deref(), Some(&3));
+    /// // Asking for the wrong type doesn't panic
+    /// assert!(client.query_data::<_, String>("count").is_none());
+    /// ```
+    pub fn query_data<K: AsKeys, T: 'static>(&self, key: K) -> Option<Rc<T>> {
+        self.try_query_data(key).ok().flatten()
+    }
+
+    /// Like [`query_data`](QueryClient::query_data), but returns an error
+    /// describing the mismatch if the cached data isn't a `T`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # let client = QueryClient::new(ClientOptions::default());
+    /// client.set_query_data("count", 3u32);
+    /// assert_eq!(client.try_query_data::<_, u32>("count"), Ok(Some(std::rc::Rc::new(3))));
+    /// let err = client.try_query_data::<_, String>("count").unwrap_err();
+    /// # #[cfg(feature = "readable-keys")]
+    /// assert!(err.to_string().contains("\"count\""));
+    /// ```
+    pub fn try_query_data<K: AsKeys, T: 'static>(
+        &self,
+        key: K,
+    ) -> Result<Option<Rc<T>>, TypeMismatch> {
+        let id = key.as_keys();
+        self.register_key_repr(&id, &key);
+        let data = self.cache.read().unwrap().get(&id);
+        match data {
+            Some(data) => data
+                .downcast()
+                .map(Some)
+                .map_err(|_| self.mismatch_error::<T>(&id)),
+            None => Ok(None),
+        }
+    }
+
+    /// Override the query data in the cache for a given key. This will update
+    /// all queries with the same key automatically to reflect the new data.
+    pub fn set_query_data<K: AsKeys, T: 'static>(&self, key: K, value: T) {
+        self.set_query_data_with_options(key, value, SetDataOptions::default());
+    }
+
+    /// Override the query data in the cache for a given key with extra
+    /// options. For more information see [`set_query_data`](QueryClient::set_query_data)
+    /// and [`SetDataOptions`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # let client = QueryClient::new(ClientOptions::default());
+    /// client.set_query_data(("user", 1), "Bob".to_string());
+    /// // Server confirmed the new name, but the rest of the user might still be
+    /// // outdated, so keep the original expiration time.
+    /// client.set_query_data_with_options(
+    ///     ("user", 1),
+    ///     "Alice".to_string(),
+    ///     SetDataOptions { preserve_timestamp: true },
+    /// );
+    /// assert_eq!(
+    ///     client.query_data::<_, String>(("user", 1)).as_deref(),
+    ///     Some(&"Alice".to_string())
+    /// );
+    /// ```
+    pub fn set_query_data_with_options<K: AsKeys, T: 'static>(
+        &self,
+        key: K,
+        value: T,
+        options: SetDataOptions,
+    ) {
+        let id = key.as_keys();
+        self.register_key_repr(&id, &key);
+        self.register_type::<T>(&id);
+        self.set_erased_query_data(id, Rc::new(value), options);
+    }
+
+    /// Put all queries with the given key into the error state, as if their
+    /// last fetch had failed with `err`. The failure path counterpart of
+    /// [`set_query_data`](QueryClient::set_query_data), useful for stories,
+    /// tests and demoing error UI. No fetch is run.
+    ///
+    /// Like failed fetches, errors aren't cached: the cached data is left
+    /// untouched and only mounted queries show the error. Queries that are
+    /// currently fetching show the error until their fetch settles.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{*, query::use_query};
+    /// create_scope_immediate(|cx| {
+    ///     let client = QueryClient::new(ClientOptions {
+    ///         # ssr: true,
+    ///         ..Default::default()
+    ///     });
+    ///     provide_context(cx, client.clone());
+    ///     client.set_query_data("user", "Ferris".to_string());
+    ///     let user = use_query(cx, "user", || async { Ok::<_, String>("Ferris".to_string()) });
+    ///
+    ///     client.set_query_error("user", "Not found".to_string());
+    ///     assert_eq!(user.data.get_data().err().as_deref(), Some(&"Not found".to_string()));
+    ///     assert_eq!(*user.status.get(), Status::Success);
+    ///     assert_eq!(*user.data_source.get(), DataSource::Manual);
+    /// });
+    /// ```
+    pub fn set_query_error<K: AsKeys, E: 'static>(&self, key: K, err: E) {
+        let id = key.as_keys();
+        self.register_key_repr(&id, &key);
+        let err: Rc<dyn Any> = Rc::new(err);
+        self.write_data(
+            id,
+            DataSource::Manual,
+            QueryData::Err(err),
+            Some(Status::Success),
+        );
+    }
+
+    /// Override the query data of every query whose key starts with
+    /// `prefix`, the bulk version of
+    /// [`set_query_data`](QueryClient::set_query_data). `updater` is called
+    /// with the key and current data of each cached query, and of each
+    /// mounted query that has no data yet (as `None`). It returns the new data,
+    /// or `None` to leave the query untouched. Queries holding data of another
+    /// type than `T`, like the details under a list's key, are skipped. All
+    /// changes are applied at once, see
+    /// [`update_queries`](QueryClient::update_queries).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{*, query::use_query};
+    /// # use std::rc::Rc;
+    /// # async fn fetch_followers() -> Result<Vec<u32>, String> { Ok(vec![]) }
+    /// # let client = QueryClient::new(ClientOptions { ssr: true, ..Default::default() });
+    /// #[derive(Clone)]
+    /// struct Notification {
+    ///     read: bool,
+    /// }
+    ///
+    /// client.set_query_data(("notifications", 1), vec![Notification { read: false }]);
+    /// client.set_query_data(("notifications", 2), vec![Notification { read: false }]);
+    ///
+    /// // Mark all notifications read
+    /// client.set_queries_data("notifications", |_, page: Option<Rc<Vec<Notification>>>| {
+    ///     let page = page?;
+    ///     Some(page.iter().map(|_| Notification { read: true }).collect())
+    /// });
+    /// let page = client.query_data::<_, Vec<Notification>>(("notifications", 2)).unwrap();
+    /// assert!(page[0].read);
+    ///
+    /// // Other types under the prefix are left alone
+    /// client.set_query_data(("user", 1), "Alice".to_string());
+    /// client.set_query_data(("user", 1, "posts"), vec![1u32, 2]);
+    /// client.set_queries_data(("user", 1), |_, name: Option<Rc<String>>| {
+    ///     Some(name?.to_uppercase())
+    /// });
+    /// assert_eq!(client.query_data::<_, String>(("user", 1)).as_deref(), Some(&"ALICE".to_string()));
+    /// assert_eq!(client.query_data::<_, Vec<u32>>(("user", 1, "posts")).as_deref(), Some(&vec![1, 2]));
+    ///
+    /// // Even while they're still loading
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, client.clone());
+    ///     let followers = use_query(cx, ("user", 1, "followers"), fetch_followers);
+    ///     client.set_queries_data(("user", 1), |_, name: Option<Rc<String>>| {
+    ///         Some(name.map_or_else(|| "Unknown".to_string(), |name| name.to_lowercase()))
+    ///     });
+    ///     assert_eq!(*followers.data.get(), QueryData::Loading);
+    /// });
+    /// ```
+    pub fn set_queries_data<K: AsKeys, T: 'static>(
+        &self,
+        prefix: K,
+        updater: impl Fn(&QueryKey, Option<Rc<T>>) -> Option<T>,
+    ) {
+        let id = prefix.as_keys();
+        self.register_key_repr(&id, &prefix);
+        let cached = self.cache.read().unwrap().matching(&id);
+        let without_data: Vec<_> = self
+            .mounted_matching(std::slice::from_ref(&id))
+            .into_iter()
+            .filter(|key| cached.iter().all(|(cached, _)| cached != key))
+            .filter(|key| !self.registered_other_type::<T>(key))
+            .map(|key| (key, None))
+            .collect();
+        let mut entries: Vec<_> = cached
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, Some(value.downcast::<T>().ok()?))))
+            .collect();
+        entries.extend(without_data);
+        self.update_queries(|update| {
+            for (key, value) in entries {
+                if let Some(value) = updater(&key, value) {
+                    update.set(key, value);
+                }
+            }
+        });
+    }
+
+    /// Restore query data that was persisted elsewhere, for example rendered
+    /// on the server or saved to `localStorage`. `age` is how long ago the
+    /// data was fetched, so it goes stale and expires at the same time it
+    /// would have in the original cache. A query mounted while hydrated data
+    /// is still fresh (see [`ClientOptions::stale_time`]) uses it without
+    /// fetching. Data older than the cache expiration is ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{*, query::use_query, testing::TestQueryClient};
+    /// # use std::time::Duration;
+    /// # async fn fetch_user() -> Result<String, String> { unreachable!() }
+    /// # async fn fetch_feed() -> Result<Vec<u32>, String> { unreachable!() }
+    /// let test = TestQueryClient::with_options(ClientOptions {
+    ///     stale_time: Duration::from_secs(60),
+    ///     ..ClientOptions::test_defaults()
+    /// });
+    /// let client = test.client();
+    /// // Fetched on the server 5 seconds ago
+    /// client.hydrate_query_data("user", "Ferris".to_string(), Duration::from_secs(5));
+    /// // Saved to `localStorage` 2 minutes ago, so it's stale
+    /// client.hydrate_query_data("feed", vec![1u32, 2], Duration::from_secs(2 * 60));
+    /// // Expired a long time ago
+    /// client.hydrate_query_data("posts", vec![1, 2, 3], Duration::from_secs(60 * 60));
+    /// assert!(client.query_data::<_, Vec<u32>>("posts").is_none());
+    ///
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, client.clone());
+    ///     let user = use_query(cx, "user", fetch_user);
+    ///     assert_eq!(user.data.get_data().ok().as_deref(), Some(&"Ferris".to_string()));
+    ///     assert_eq!(*user.data_source.get(), DataSource::Cache);
+    ///     assert!(test.pending_fetches().is_empty());
+    ///
+    ///     // Stale data is shown while it's refetched
+    ///     let feed = use_query(cx, "feed", fetch_feed);
+    ///     assert_eq!(feed.data.get_data().ok().as_deref(), Some(&vec![1, 2]));
+    ///     assert_eq!(test.pending_fetches(), vec!["feed".as_keys()]);
+    /// });
+    /// # }
+    /// ```
+    pub fn hydrate_query_data<K: AsKeys, T: 'static>(&self, key: K, value: T, age: Duration) {
+        if age > self.default_options.cache_expiration {
+            return;
+        }
+        let id = key.as_keys();
+        self.register_key_repr(&id, &key);
+        self.register_type::<T>(&id);
+        self.hydrate_erased_query_data(id, Rc::new(value), age);
+    }
+
+    /// Type erased version of
+    /// [`hydrate_query_data`](QueryClient::hydrate_query_data), for data
+    /// that's already been checked against `cache_expiration`.
+    pub(crate) fn hydrate_erased_query_data(
+        &self,
+        key: QueryKey,
+        value: Rc<dyn Any>,
+        age: Duration,
+    ) {
+        self.cache.write().unwrap().insert_with_age(
+            key.clone(),
+            value.clone(),
+            age,
+            &self.default_options,
+        );
+        self.write_data(key.clone(), DataSource::Cache, QueryData::Ok(value), None);
+        self.emit(|| QueryEvent::Updated { key });
+        self.cache_changed();
+    }
+
+    /// Write type erased data to the cache and notify queries with the same
+    /// key. The caller is responsible for registering the type.
+    pub(crate) fn set_erased_query_data(
+        &self,
+        key: QueryKey,
+        value: Rc<dyn Any>,
+        options: SetDataOptions,
+    ) {
+        {
+            let mut cache = self.cache.write().unwrap();
+            if options.preserve_timestamp {
+                cache.replace(key.clone(), value.clone(), &self.default_options);
+            } else {
+                cache.insert(key.clone(), value.clone(), &self.default_options);
+            }
+        }
+        self.write_data(key.clone(), DataSource::Manual, QueryData::Ok(value), None);
+        self.emit(|| QueryEvent::Updated { key });
+        self.cache_changed();
+    }
+
+    /// Update the data of multiple queries at once, for example to write an
+    /// entity returned by a mutation to both its detail query and every list
+    /// query containing it. All changes are written to the cache before any
+    /// query is notified, so no query ever sees a partially applied update.
+    /// See [`CacheUpdate`] for the available operations.
+    ///
+    /// Prefixes work the same way as in
+    /// [`invalidate_queries`](QueryClient::invalidate_queries). Updated queries
+    /// can still be invalidated afterwards, in which case they show the
+    /// updated data until the refetch finishes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # let client = QueryClient::new(ClientOptions::default());
+    /// #[derive(Clone, PartialEq, Debug)]
+    /// struct Todo {
+    ///     id: u32,
+    ///     title: String,
+    /// }
+    ///
+    /// # let todo = |title: &str| Todo { id: 1, title: title.to_string() };
+    /// client.set_query_data(("todos", "open"), vec![todo("Write docs")]);
+    /// // Skipped by `update_matching` below, since it's not a `Vec<Todo>`
+    /// client.set_query_data(("todos", "open", "count"), 1u32);
+    /// client.set_query_data(("todo", 1), todo("Write docs"));
+    ///
+    /// // In the `on_success` of a mutation that renamed the todo
+    /// let updated = todo("Write better docs");
+    /// client.update_queries(|update| {
+    ///     update.set(("todo", updated.id), updated.clone());
+    ///     update.update_matching("todos", |todos: &Vec<Todo>| {
+    ///         let index = todos.iter().position(|todo| todo.id == updated.id)?;
+    ///         let mut todos = todos.clone();
+    ///         todos[index] = updated.clone();
+    ///         Some(todos)
+    ///     });
+    /// });
+    ///
+    /// assert_eq!(
+    ///     client.query_data::<_, Vec<Todo>>(("todos", "open")).unwrap()[0],
+    ///     updated
+    /// );
+    /// ```
+    pub fn update_queries(&self, update: impl FnOnce(&mut CacheUpdate<'_>)) {
+        let mut batch = CacheUpdate {
+            client: self,
+            updates: FnvHashMap::default(),
+        };
+        update(&mut batch);
+
+        {
+            let mut cache = self.cache.write().unwrap();
+            for (key, (value, preserve_timestamp)) in &batch.updates {
+                if *preserve_timestamp {
+                    cache.replace(key.clone(), value.clone(), &self.default_options);
+                } else {
+                    cache.insert(key.clone(), value.clone(), &self.default_options);
+                }
+            }
+        }
+        let mounted: Vec<_> = {
+            let state_signals = self.state_signals.read().unwrap();
+            batch
+                .updates
+                .into_iter()
+                .filter(|(key, _)| state_signals.contains_key(key))
+                .collect()
+        };
+        for (key, (value, _)) in mounted {
+            self.write_data(key.clone(), DataSource::Manual, QueryData::Ok(value), None);
+            self.emit(|| QueryEvent::Updated { key });
+        }
+        self.cache_changed();
+    }
+
+    /// Run `f` and defer the signal updates of everything it changes until
+    /// it returns, so components render once instead of after every change.
+    /// Only the last data written to each key is shown. Changes are still
+    /// written to the cache right away, so
+    /// [`query_data`](QueryClient::query_data) sees them inside the batch.
+    /// Batches can be nested, updates are applied when the outermost one
+    /// returns.
+    ///
+    /// The same goes for [`invalidate_queries`](QueryClient::invalidate_queries):
+    /// the cached data is removed right away, but the refetches start when
+    /// the batch returns, and each query is refetched once no matter how many
+    /// overlapping prefixes invalidated it.
+    ///
+    /// If `f` panics, the updates and refetches it deferred are dropped. The
+    /// cache keeps its changes, and later updates are applied right away
+    /// again.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, *};
+    /// # use std::{cell::Cell, rc::Rc};
+    /// let client = QueryClient::new(ClientOptions::default());
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, client.clone());
+    ///     let count = QueryBuilder::new("count", || async { Ok::<_, ()>(0u32) })
+    ///         .enabled(|| false)
+    ///         .use_query(cx);
+    ///     let renders = Rc::new(Cell::new(0));
+    ///     create_effect(cx, {
+    ///         let renders = renders.clone();
+    ///         move || {
+    ///             count.data.track();
+    ///             renders.set(renders.get() + 1);
+    ///         }
+    ///     });
+    ///     renders.set(0);
+    ///
+    ///     for delta in 1..=3u32 {
+    ///         client.set_query_data("count", delta);
+    ///     }
+    ///     assert_eq!(renders.get(), 3);
+    ///
+    ///     client.batch(|client| {
+    ///         for delta in 4..=6u32 {
+    ///             client.set_query_data("count", delta);
+    ///         }
+    ///         assert_eq!(client.query_data::<_, u32>("count").as_deref(), Some(&6));
+    ///     });
+    ///     assert_eq!(renders.get(), 4);
+    ///     assert_eq!(count.data.get_data().ok().as_deref(), Some(&6));
+    /// });
+    /// ```
+    ///
+    /// Invalidating overlapping prefixes, like a mutation's `on_success` might:
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, testing::TestQueryClient, *};
+    /// # async fn fetch_todo(id: u32) -> Result<String, String> { unreachable!() }
+    /// let test = TestQueryClient::new();
+    /// let client = test.client();
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, client.clone());
+    ///     for id in 1..=2 {
+    ///         use_query(cx, ("todos", id), move || fetch_todo(id));
+    ///         test.resolve(("todos", id), format!("Todo {id}"));
+    ///     }
+    ///
+    ///     client.batch(|client| {
+    ///         client.invalidate_queries("todos");
+    ///         client.invalidate_queries(("todos", 1));
+    ///         client.invalidate_queries(keys!["todos", ("todos", 2)]);
+    ///         assert!(test.pending_fetches().is_empty());
+    ///     });
+    ///     // One refetch per query
+    ///     assert_eq!(test.pending_fetches().len(), 2);
+    ///     assert_eq!(client.fetch_log().len(), 4);
+    /// });
+    /// # }
+    /// ```
+    ///
+    /// A batch that panics doesn't defer anything after it:
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, *};
+    /// # use std::panic::{catch_unwind, AssertUnwindSafe};
+    /// let client = QueryClient::new(ClientOptions::default());
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, client.clone());
+    ///     let count = QueryBuilder::new("count", || async { Ok::<_, ()>(0u32) })
+    ///         .enabled(|| false)
+    ///         .use_query(cx);
+    ///     let _ = catch_unwind(AssertUnwindSafe(|| {
+    ///         client.batch(|client| {
+    ///             client.set_query_data("count", 1u32);
+    ///             panic!("failed halfway");
+    ///         })
+    ///     }));
+    ///     client.set_query_data("count", 2u32);
+    ///     assert_eq!(count.data.get_data().ok().as_deref(), Some(&2));
+    /// });
+    /// ```
+    pub fn batch<R>(&self, f: impl FnOnce(&Self) -> R) -> R {
+        let result = {
+            let _batch = BatchScope::enter(self);
+            f(self)
+        };
+        let pending = {
+            let mut pending = self.pending_writes.borrow_mut();
+            if pending.depth > 0 {
+                return result;
+            }
+            std::mem::take(&mut *pending)
+        };
+        for (key, source, value, status) in pending.data {
+            self.write_data(key, source, value, status);
+        }
+        if !pending.invalidated.is_empty() || !pending.unused.is_empty() {
+            // Prefixes covered by a shorter one would only match the same
+            // queries again
+            let prefixes: Vec<_> = pending
+                .invalidated
+                .iter()
+                .enumerate()
+                .filter(|(i, prefix)| {
+                    !pending.invalidated.iter().enumerate().any(|(j, other)| {
+                        prefix.starts_with(other) && (other.len() < prefix.len() || j < *i)
+                    })
+                })
+                .map(|(_, prefix)| prefix.clone())
+                .collect();
+            let mut unused = pending.unused;
+            unused.sort_by_key(QueryKey::to_bytes);
+            unused.dedup();
+            self.refetch_invalidated(&prefixes, unused);
+        }
+        if pending.cache_changed {
+            self.cache_changed();
+        }
+        result
+    }
+
+    /// Show `value` in the mounted queries with `key`, or remember it for
+    /// the end of the current [`batch`](QueryClient::batch). `status` is set
+    /// in the same write, unless the query is fetching.
+    pub(crate) fn write_data(
+        &self,
+        key: QueryKey,
+        source: DataSource,
+        value: ErasedData,
+        status: Option<Status>,
+    ) {
+        {
+            let mut pending = self.pending_writes.borrow_mut();
+            if pending.depth > 0 {
+                match pending
+                    .data
+                    .iter_mut()
+                    .find(|(existing, _, _, _)| *existing == key)
+                {
+                    Some(write) => *write = (key, source, value, status.or(write.3)),
+                    None => pending.data.push((key, source, value, status)),
+                }
+                return;
+            }
+        }
+        self.set_data_source(&key, source);
+        let state = self.state_signals.read().unwrap().get(&key);
+        if let Some(state) = state {
+            let status = match (state.get_untracked().1, status) {
+                (Status::Fetching, _) | (_, None) => state.get_untracked().1,
+                (_, Some(status)) => status,
+            };
+            state.set((value, status));
+        }
+    }
+
+    /// Get the state of all mutations tracked by the client. This includes
+    /// in-flight mutations as well as settled mutations that haven't been
+    /// garbage collected yet, in the order they were started.
+    /// Mutations keep running and are tracked here even if the component that
+    /// started them has been unmounted.
+    pub fn mutations(&self) -> Vec<MutationState> {
+        self.mutation_cache
+            .read()
+            .unwrap()
+            .iter()
+            .map(MutationState::from)
+            .collect()
+    }
+
+    /// Get the state of all mutations whose `mutation_key` starts with `key`.
+    /// See [`mutations`](QueryClient::mutations) for details.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # let client = QueryClient::new(ClientOptions::default());
+    /// let pending = client
+    ///     .mutations_with_key("todos")
+    ///     .into_iter()
+    ///     .filter(|mutation| mutation.status == Status::Fetching)
+    ///     .count();
+    /// assert_eq!(pending, 0);
+    /// ```
+    pub fn mutations_with_key<K: AsKeys>(&self, key: K) -> Vec<MutationState> {
+        let key = key.as_keys();
+        self.mutation_cache
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|entry| {
+                entry
+                    .key
+                    .as_ref()
+                    .is_some_and(|mutation_key| mutation_key.starts_with(&key))
+            })
+            .map(MutationState::from)
+            .collect()
+    }
+
+    /// Get the state of all mutations that were started while the client was
+    /// offline and haven't been sent yet, in the order they will be replayed.
+    pub fn paused_mutations(&self) -> Vec<MutationState> {
+        self.mutations()
+            .into_iter()
+            .filter(|mutation| mutation.status == Status::Paused)
+            .collect()
+    }
+
+    /// A signal with the number of paused mutations waiting to be replayed.
+    /// Useful for showing something like "3 changes pending sync".
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # let client = QueryClient::new(ClientOptions::default());
+    /// let pending = client.paused_mutation_count();
+    /// assert_eq!(*pending.get(), 0);
+    /// ```
+    pub fn paused_mutation_count(&self) -> &ReadSignal<usize> {
+        &self.paused_mutation_count
+    }
+
+    /// Whether the client currently considers the network available. This
+    /// follows the [`OnlineManager`] set in [`ClientOptions::online_manager`],
+    /// unless overridden with [`set_online`](QueryClient::set_online).
+    pub fn is_online(&self) -> bool {
+        !self.offline.get()
+    }
+
+    /// Tell the client whether the network is available. Mutations started
+    /// while the client is offline are paused (see [`Status::Paused`]) and
+    /// replayed in the order they were started once the client is set back
+    /// online. This is called automatically when the [`OnlineManager`]
+    /// reports a change, and the next report overrides a manual call.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # let client = QueryClient::new(ClientOptions::default());
+    /// client.set_online(false);
+    /// assert!(!client.is_online());
+    /// ```
+    pub fn set_online(&self, online: bool) {
+        self.offline.set(!online);
+        if online {
+            self.resume_paused_mutations();
+        }
+    }
+
+    /// Replay all paused mutations in the order they were started, one at a
+    /// time. Replaying stops if the client goes offline again, leaving the
+    /// remaining mutations paused. This is called automatically by
+    /// [`set_online`](QueryClient::set_online).
+    pub fn resume_paused_mutations(&self) {
+        if !self.is_online()
+            || self.mutation_cache.read().unwrap().paused_count() == 0
+            || self.resuming_mutations.replace(true)
+        {
+            return;
+        }
+        let client = self.rc();
+        self.spawn(async move {
+            while client.is_online() {
+                let mutation = client.mutation_cache.write().unwrap().next_paused();
+                let Some(mutation) = mutation else {
+                    break;
+                };
+                client.update_paused_mutation_count();
+                mutation().await;
+            }
+            client.resuming_mutations.set(false);
+        });
+    }
+
+    /// The data source signal for `key`, created if it doesn't exist yet.
+    pub(crate) fn data_source_signal(&self, key: &QueryKey) -> Rc<RcSignal<DataSource>> {
+        if let Some(signal) = self.source_signals.read().unwrap().get(key) {
+            return signal;
+        }
+        let signal = Rc::new(create_rc_signal(DataSource::None));
+        self.source_signals
+            .write()
+            .unwrap()
+            .insert(key.clone(), signal.clone());
+        signal
+    }
+
+    pub(crate) fn set_data_source(&self, key: &QueryKey, source: DataSource) {
+        let signal = self.source_signals.read().unwrap().get(key);
+        if let Some(signal) = signal.filter(|signal| *signal.get_untracked() != source) {
+            signal.set(source);
+        }
+    }
+
+    pub(crate) fn update_paused_mutation_count(&self) {
+        let count = self.mutation_cache.read().unwrap().paused_count();
+        self.paused_mutation_count.set(count);
+    }
+
+    /// Store the human readable representation of `key` so it can be used in
+    /// log output and panic messages. Does nothing if the `readable-keys`
+    /// feature is disabled.
+    #[allow(unused_variables)]
+    pub(crate) fn register_key_repr<K: AsKeys + ?Sized>(&self, id: &QueryKey, key: &K) {
+        #[cfg(feature = "readable-keys")]
+        if !self.key_reprs.read().unwrap().contains_key(id) {
+            if let Some(repr) = key.key_repr() {
+                self.key_reprs.write().unwrap().insert(id.clone(), repr);
+            }
+        }
+    }
+
+    /// The human readable representation of a key if one was registered,
+    /// otherwise the hashed key.
+    pub(crate) fn key_repr(&self, id: &QueryKey) -> String {
+        #[cfg(feature = "readable-keys")]
+        if let Some(repr) = self.key_reprs.read().unwrap().get(id) {
+            return repr.clone();
+        }
+        format!("{id:?}")
+    }
+
+    /// Pass an event to the [`Logger`], if there is one.
+    pub(crate) fn log(&self, event: impl FnOnce(&dyn Logger)) {
+        if let Some(logger) = &self.default_options.logger {
+            event(logger.as_ref());
+        }
+    }
+
+    /// Get the client from the context, panicking with a helpful message if
+    /// none was provided. `hook` is the name of the calling hook.
+    #[track_caller]
+    pub(crate) fn from_context(cx: Scope, hook: &str) -> Rc<Self> {
+        match try_use_context::<Rc<QueryClient>>(cx) {
+            Some(client) => client.clone(),
+            None => panic!(
+                "`{hook}` was called without a `QueryClient` in the context. \
+                Call `provide_context(cx, QueryClient::new(ClientOptions::default()))` \
+                in a parent component, ideally your top level component."
+            ),
+        }
+    }
+
+    fn mismatch_error<T>(&self, id: &QueryKey) -> TypeMismatch {
+        let found = self
+            .data_types
+            .read()
+            .unwrap()
+            .get(id)
+            .map(|(_, type_name)| *type_name);
+        TypeMismatch {
+            key: self.key_repr(id),
+            expected: type_name::<T>(),
+            found,
+        }
+    }
+
+    pub(crate) fn type_mismatch<T>(&self, id: &QueryKey) -> ! {
+        panic!("{}", self.mismatch_error::<T>(id))
+    }
+
+    /// Whether the data for `id` was registered with another type than `T`,
+    /// see [`register_type`](Self::register_type).
+    pub(crate) fn registered_other_type<T: 'static>(&self, id: &QueryKey) -> bool {
+        self.data_types
+            .read()
+            .unwrap()
+            .get(id)
+            .is_some_and(|(type_id, _)| *type_id != std::any::TypeId::of::<T>())
+    }
+
+    /// Record that the data for `id` is a `T`. In debug builds, panic if it
+    /// was registered with a different type before. This catches key
+    /// collisions where the second query is created instead of where the data
+    /// is read.
+    pub(crate) fn register_type<T: 'static>(&self, id: &QueryKey) {
+        let existing = self.data_types.read().unwrap().get(id).copied();
+        match existing {
+            Some((type_id, _)) if type_id != std::any::TypeId::of::<T>() => {
+                if cfg!(debug_assertions) {
+                    panic!("{}", self.mismatch_error::<T>(id))
+                }
+            }
+            Some(_) => {}
+            None => {
+                self.data_types
+                    .write()
+                    .unwrap()
+                    .insert(id.clone(), (std::any::TypeId::of::<T>(), type_name::<T>()));
+            }
+        }
+    }
+}
+
+/// A set of changes to the query cache that's applied at once. Created by
+/// [`QueryClient::update_queries`].
+pub struct CacheUpdate<'a> {
+    client: &'a QueryClient,
+    updates: FnvHashMap<QueryKey, (Rc<dyn Any>, bool)>,
+}
+
+impl<'a> CacheUpdate<'a> {
+    /// Set the data for `key`, like [`QueryClient::set_query_data`].
+    pub fn set<K: AsKeys, T: 'static>(&mut self, key: K, value: T) {
+        let id = key.as_keys();
+        self.client.register_key_repr(&id, &key);
+        self.client.register_type::<T>(&id);
+        self.updates
+            .insert(id, (Rc::new(value) as Rc<dyn Any>, false));
+    }
+
+    /// Update the data of every cached query whose key starts with `prefix`.
+    /// `update` is called with the current data, including changes made
+    /// earlier in this update, and returns the new data or `None` to leave
+    /// the query untouched. Queries without data or with data of another type
+    /// than `T` are skipped. Updated entries
+    /// keep their cache timestamp, since the rest of their data isn't any
+    /// fresher than before.
+    pub fn update_matching<K: AsKeys, T: 'static>(
+        &mut self,
+        prefix: K,
+        update: impl Fn(&T) -> Option<T>,
+    ) {
+        let id = prefix.as_keys();
+        self.client.register_key_repr(&id, &prefix);
+        let mut entries = self.client.cache.read().unwrap().matching(&id);
+        for (key, (value, _)) in &self.updates {
+            if key.starts_with(&id) {
+                entries.retain(|(existing, _)| existing != key);
+                entries.push((key.clone(), value.clone()));
+            }
+        }
+        for (key, value) in entries {
+            let Ok(value) = value.downcast::<T>() else {
+                continue;
+            };
+            if let Some(value) = update(&value) {
+                let preserve_timestamp = self
+                    .updates
+                    .get(&key)
+                    .is_none_or(|(_, preserve_timestamp)| *preserve_timestamp);
+                self.updates
+                    .insert(key, (Rc::new(value) as Rc<dyn Any>, preserve_timestamp));
+            }
+        }
+    }
+}
+
+/// Collect garbage every time `trigger` changes, for example on navigation.
+/// Garbage is collected after the current update has finished, so queries
+/// that are unmounted because of the change have already been dropped and
+/// their memory is reclaimed. Nothing is collected when the hook is first
+/// used.
+///
+/// # Panics
+///
+/// Panics if no [`QueryClient`] was provided as a context.
+///
+/// # Example
+///
+/// With `sycamore-router`, pass the route signal from your router's `view`:
+///
+/// ```text
+/// view! { cx,
+///     Router(
+///         integration=HistoryIntegration::new(),
+///         view=|cx, route: &ReadSignal<AppRoutes>| {
+///             use_collect_garbage_on(cx, route);
+///             view! { cx,
+///                 (match route.get().as_ref() {
+///                     AppRoutes::Index => view! { cx, Index },
+///                     AppRoutes::NotFound => view! { cx, "404" },
+///                 })
+///             }
+///         }
+///     )
+/// }
+/// ```
+///
+/// Any other signal works as well:
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::*;
+/// # #[component]
+/// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+/// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+/// let page = create_signal(cx, 1u32);
+/// use_collect_garbage_on(cx, page);
+/// # view! { cx, }
+/// # }
+/// ```
+pub fn use_collect_garbage_on<'a, T>(cx: Scope<'a>, trigger: &'a ReadSignal<T>) {
+    let client = QueryClient::from_context(cx, "use_collect_garbage_on");
+    let mut first_run = true;
+    create_effect(cx, move || {
+        trigger.track();
+        if std::mem::take(&mut first_run) || client.default_options.ssr {
+            return;
+        }
+        let client = client.clone();
+        // Defer until the old page has been disposed and its queries dropped
+        client
+            .clone()
+            .spawn(async move { client.collect_garbage() });
+    });
 }
@@ -0,0 +1,115 @@
+use std::rc::Rc;
+
+use sycamore::prelude::*;
+
+use crate::{QueryData, Status};
+
+type StateView<'a, G> = Box<dyn Fn(BoundedScope<'_, 'a>) -> View<G> + 'a>;
+type ValueView<'a, V, G> = Box<dyn Fn(BoundedScope<'_, 'a>, Rc<V>) -> View<G> + 'a>;
+
+/// Props for [`QueryBoundary`].
+///
+/// # Options
+///
+/// * `data` - The `data` signal of a [`Query`](crate::query::Query)
+/// * `loading` - Rendered while the query is loading for the first time
+/// * `error` - Rendered with the error if the query failed
+/// * `view` - Rendered with the data once the query succeeded
+/// * `status` - The `status` signal of the query. If set, `refreshing` is
+///   rendered after the data while a background fetch is running.
+///   (Default: `None`)
+/// * `refreshing` - Rendered while refreshing, see `status`.
+///   (Default: a `span` reading "Refreshing...")
+#[derive(Prop)]
+pub struct QueryBoundaryProps<'a, T: 'static, E: 'static, G: Html> {
+    data: &'a ReadSignal<QueryData<Rc<T>, Rc<E>>>,
+    #[builder(setter(transform = |f: impl Fn(BoundedScope<'_, 'a>) -> View<G> + 'a| Box::new(f) as StateView<'a, G>))]
+    loading: StateView<'a, G>,
+    #[builder(setter(transform = |f: impl Fn(BoundedScope<'_, 'a>, Rc<E>) -> View<G> + 'a| Box::new(f) as ValueView<'a, E, G>))]
+    error: ValueView<'a, E, G>,
+    #[builder(setter(transform = |f: impl Fn(BoundedScope<'_, 'a>, Rc<T>) -> View<G> + 'a| Box::new(f) as ValueView<'a, T, G>))]
+    view: ValueView<'a, T, G>,
+    #[builder(default, setter(strip_option))]
+    status: Option<&'a ReadSignal<Status>>,
+    #[builder(default, setter(transform = |f: impl Fn(BoundedScope<'_, 'a>) -> View<G> + 'a| Some(Box::new(f) as StateView<'a, G>)))]
+    refreshing: Option<StateView<'a, G>>,
+}
+
+/// Renders the state of a query, so components don't need to match on
+/// [`QueryData`] themselves. Shows `loading` until the first fetch settles,
+/// then either `error` or `view`, updating whenever the data changes. Idle
+/// queries (see [`QueryBuilder::enabled`](crate::query::QueryBuilder::enabled))
+/// render nothing. Pass the query's `status` to also show an indicator while
+/// existing data is refreshed in the background.
+///
+/// See [`QueryBoundaryProps`] for all props.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, *, components::QueryBoundary};
+/// # async fn fetch_user(id: u32) -> Result<String, String> { Ok(id.to_string()) }
+/// #[component]
+/// fn User<G: Html>(cx: Scope) -> View<G> {
+///     let Query { data, status, .. } = use_query(cx, ("user", 1), || fetch_user(1));
+///
+///     view! { cx,
+///         QueryBoundary(
+///             data=data,
+///             status=status,
+///             loading=|cx| view! { cx, "Loading..." },
+///             error=|cx, err| view! { cx, "Failed: " (err) },
+///             view=|cx, user| view! { cx, "Hello, " (user) },
+///         )
+///     }
+/// }
+///
+/// let client = QueryClient::new(ClientOptions {
+///     ssr: true,
+///     ..Default::default()
+/// });
+/// client.hydrate_query_data(("user", 1), "Alice".to_string(), Default::default());
+/// let html = sycamore::render_to_string(|cx| {
+///     provide_context(cx, client);
+///     view! { cx, User {} }
+/// });
+/// assert!(html.contains("Hello, Alice"));
+/// ```
+#[component]
+pub fn QueryBoundary<'a, T: 'static, E: 'static, G: Html>(
+    cx: Scope<'a>,
+    props: QueryBoundaryProps<'a, T, E, G>,
+) -> View<G> {
+    let QueryBoundaryProps {
+        data,
+        loading,
+        error,
+        view,
+        status,
+        refreshing,
+    } = props;
+
+    let body = View::new_dyn_scoped(cx, move |cx| match data.get().as_ref() {
+        QueryData::Loading => loading(cx),
+        QueryData::Idle => View::empty(),
+        QueryData::Err(err) => error(cx, err.clone()),
+        QueryData::Ok(value) => view(cx, value.clone()),
+    });
+    let Some(status) = status else {
+        return body;
+    };
+    let is_refreshing = create_selector(cx, move || {
+        matches!(data.get().as_ref(), QueryData::Ok(_)) && *status.get() == Status::Fetching
+    });
+    let overlay = View::new_dyn_scoped(cx, move |cx| {
+        if !*is_refreshing.get() {
+            return View::empty();
+        }
+        match &refreshing {
+            Some(refreshing) => refreshing(cx),
+            None => view! { cx, span { "Refreshing..." } },
+        }
+    });
+    View::new_fragment(vec![body, overlay])
+}
@@ -0,0 +1,191 @@
+use std::{rc::Rc, time::Duration};
+
+use sycamore::prelude::*;
+
+use crate::{QueryClient, QueryData, QueryKey, Status};
+
+const PANEL_STYLE: &str = "position: fixed; bottom: 0; right: 0; z-index: 99999; \
+    max-height: 50vh; max-width: 100vw; overflow: auto; background: #fff; color: #222; \
+    border: 1px solid #ccc; font: 12px monospace;";
+const TABLE_STYLE: &str = "border-collapse: collapse;";
+const CELL_STYLE: &str = "padding: 2px 6px; border-top: 1px solid #eee; text-align: left;";
+const BUTTON_STYLE: &str = "margin-right: 4px; font: inherit;";
+
+/// A collapsible panel listing every query the [`QueryClient`] knows about,
+/// for debugging. Shows each query's key, status, data, the age of its
+/// cached data, how many mounted queries use it and its metadata, with
+/// buttons to invalidate, refetch or remove it, and one to clear the whole
+/// cache. The list updates whenever the cache changes.
+///
+/// Mount it anywhere below the component that provides the [`QueryClient`].
+/// Only available with the `devtools` feature.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{*, devtools::QueryDevtools};
+/// #[component]
+/// pub fn App<G: Html>(cx: Scope) -> View<G> {
+///     provide_context(cx, QueryClient::new(ClientOptions::default()));
+///
+///     view! { cx,
+///         // The rest of the app
+///         QueryDevtools {}
+///     }
+/// }
+/// ```
+#[component]
+pub fn QueryDevtools<G: Html>(cx: Scope) -> View<G> {
+    let client = QueryClient::from_context(cx, "QueryDevtools");
+    let open = create_signal(cx, false);
+    let keys = {
+        let client = client.clone();
+        create_memo(cx, move || {
+            client.cache_changes().track();
+            let mut keys: Vec<_> = client
+                .query_keys()
+                .into_iter()
+                .map(|key| (client.key_repr(&key), key))
+                .collect();
+            keys.sort_by(|(a, _), (b, _)| a.cmp(b));
+            keys
+        })
+    };
+    let toggle_label = create_memo(cx, move || {
+        let label = if *open.get() { "Hide" } else { "Show" };
+        format!("{label} queries ({})", keys.get().len())
+    });
+
+    view! { cx,
+        div(style=PANEL_STYLE) {
+            div {
+                button(style=BUTTON_STYLE, on:click=move |_| open.set(!*open.get())) {
+                    (toggle_label.get())
+                }
+                button(style=BUTTON_STYLE, on:click={
+                    let client = client.clone();
+                    move |_| client.clear()
+                }) {
+                    "Clear cache"
+                }
+            }
+            (if *open.get() {
+                let client = client.clone();
+                view! { cx,
+                    table(style=TABLE_STYLE) {
+                        tr {
+                            th(style=CELL_STYLE) { "Key" }
+                            th(style=CELL_STYLE) { "Status" }
+                            th(style=CELL_STYLE) { "Data" }
+                            th(style=CELL_STYLE) { "Age" }
+                            th(style=CELL_STYLE) { "Observers" }
+                            th(style=CELL_STYLE) { "Meta" }
+                            th(style=CELL_STYLE) {}
+                        }
+                        Keyed(
+                            iterable=keys,
+                            view=move |cx, (repr, key)| query_row(cx, client.clone(), repr, key),
+                            key=|(_, key)| key.clone(),
+                        )
+                    }
+                }
+            } else {
+                view! { cx, }
+            })
+        }
+    }
+}
+
+fn query_row<G: Html>(cx: Scope, client: Rc<QueryClient>, repr: String, key: QueryKey) -> View<G> {
+    // Looked up on every change instead of held, so an open row doesn't keep
+    // an unmounted query alive
+    let status = {
+        let client = client.clone();
+        let key = key.clone();
+        create_memo(cx, move || {
+            client.cache_changes().track();
+            let state = client.state_signals.read().unwrap().get(&key);
+            state.map_or("inactive", |state| status_label(state.get().1))
+        })
+    };
+    let data = {
+        let client = client.clone();
+        let key = key.clone();
+        create_memo(cx, move || {
+            client.cache_changes().track();
+            let state = client.state_signals.read().unwrap().get(&key);
+            state.map_or("cached", |state| data_label(&state.get().0))
+        })
+    };
+    let cached = {
+        let client = client.clone();
+        let key = key.clone();
+        create_memo(cx, move || {
+            client.cache_changes().track();
+            let cache = client.cache.read().unwrap();
+            let age = cache.age(&key).map_or_else(|| "-".to_string(), format_age);
+            (age, cache.observer_count(&key))
+        })
+    };
+    let meta = client
+        .query_meta(key.clone())
+        .map_or_else(String::new, |meta| {
+            let mut entries: Vec<_> = meta.iter().map(|(k, v)| format!("{k}={v}")).collect();
+            entries.sort();
+            entries.join(", ")
+        });
+    let invalidate = {
+        let client = client.clone();
+        let key = key.clone();
+        move |_| client.invalidate_queries(vec![key.clone()])
+    };
+    let refetch = {
+        let client = client.clone();
+        let key = key.clone();
+        move |_| client.refetch_queries(vec![key.clone()], true)
+    };
+    let remove = move |_| client.remove_queries(vec![key.clone()]);
+
+    view! { cx,
+        tr {
+            td(style=CELL_STYLE) { (repr) }
+            td(style=CELL_STYLE) { (status.get()) }
+            td(style=CELL_STYLE) { (data.get()) }
+            td(style=CELL_STYLE) { (cached.get().0.clone()) }
+            td(style=CELL_STYLE) { (cached.get().1) }
+            td(style=CELL_STYLE) { (meta) }
+            td(style=CELL_STYLE) {
+                button(style=BUTTON_STYLE, on:click=invalidate) { "Invalidate" }
+                button(style=BUTTON_STYLE, on:click=refetch) { "Refetch" }
+                button(style=BUTTON_STYLE, on:click=remove) { "Remove" }
+            }
+        }
+    }
+}
+
+fn status_label(status: Status) -> &'static str {
+    match status {
+        Status::Idle => "idle",
+        Status::Fetching => "fetching",
+        Status::Success => "success",
+        Status::Paused => "paused",
+    }
+}
+
+fn data_label<T, E>(data: &QueryData<T, E>) -> &'static str {
+    match data {
+        QueryData::Loading => "loading",
+        QueryData::Idle => "idle",
+        QueryData::Ok(_) => "ok",
+        QueryData::Err(_) => "error",
+    }
+}
+
+fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        secs @ 0..=59 => format!("{secs}s"),
+        secs @ 60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        secs => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
@@ -0,0 +1,122 @@
+use std::{cell::Cell, rc::Rc};
+
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::VisibilityState;
+
+use crate::{Listeners, Subscription};
+
+/// Tells the [`QueryClient`](crate::QueryClient) whether the app is focused
+/// and when that changes. All focus driven behaviour, like
+/// [`refetch_on_window_focus`](crate::ClientOptions::refetch_on_window_focus),
+/// goes through the focus manager set in
+/// [`ClientOptions::focus_manager`](crate::ClientOptions::focus_manager), so
+/// apps that report focus some other way (like a webview bridge) can provide
+/// their own.
+///
+/// The default is [`WindowFocus`]. For tests, use [`ManualFocus`].
+pub trait FocusManager {
+    /// Whether the app is currently focused.
+    fn is_focused(&self) -> bool;
+
+    /// Call `callback` with the new focus state every time it changes, until
+    /// the returned [`Subscription`] is dropped.
+    fn subscribe(&self, callback: Rc<dyn Fn(bool)>) -> Subscription;
+}
+
+/// The default [`FocusManager`]. The app is focused while the document is
+/// visible, using the `visibilitychange` event. Outside of the browser, the
+/// app is always focused.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WindowFocus;
+
+impl FocusManager for WindowFocus {
+    fn is_focused(&self) -> bool {
+        if !cfg!(target_arch = "wasm32") {
+            return true;
+        }
+        web_sys::window()
+            .and_then(|window| window.document())
+            .is_none_or(|document| document.visibility_state() == VisibilityState::Visible)
+    }
+
+    fn subscribe(&self, callback: Rc<dyn Fn(bool)>) -> Subscription {
+        if !cfg!(target_arch = "wasm32") {
+            return Subscription::none();
+        }
+        let Some(document) = web_sys::window().and_then(|window| window.document()) else {
+            return Subscription::none();
+        };
+        let listener = Closure::<dyn Fn()>::new(move || callback(WindowFocus.is_focused()));
+        let _ = document.add_event_listener_with_callback(
+            "visibilitychange",
+            listener.as_ref().unchecked_ref(),
+        );
+        Subscription::new(move || {
+            let _ = document.remove_event_listener_with_callback(
+                "visibilitychange",
+                listener.as_ref().unchecked_ref(),
+            );
+        })
+    }
+}
+
+/// A [`FocusManager`] that's controlled with
+/// [`set_focused`](ManualFocus::set_focused). Useful for tests, or to report
+/// focus from a source the library doesn't know about. Starts out focused.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::focus::{FocusManager, ManualFocus};
+/// # use std::{cell::Cell, rc::Rc};
+/// let focus = ManualFocus::default();
+/// let changes = Rc::new(Cell::new(0));
+/// let subscription = focus.subscribe({
+///     let changes = changes.clone();
+///     Rc::new(move |_| changes.set(changes.get() + 1))
+/// });
+///
+/// focus.set_focused(false);
+/// focus.set_focused(false);
+/// assert!(!focus.is_focused());
+/// assert_eq!(changes.get(), 1);
+///
+/// drop(subscription);
+/// focus.set_focused(true);
+/// assert_eq!(changes.get(), 1);
+/// ```
+#[derive(Clone)]
+pub struct ManualFocus(Rc<ManualFocusState>);
+
+struct ManualFocusState {
+    focused: Cell<bool>,
+    listeners: Rc<Listeners>,
+}
+
+impl Default for ManualFocus {
+    fn default() -> Self {
+        Self(Rc::new(ManualFocusState {
+            focused: Cell::new(true),
+            listeners: Rc::default(),
+        }))
+    }
+}
+
+impl ManualFocus {
+    /// Set whether the app is focused, notifying subscribers if it changed.
+    pub fn set_focused(&self, focused: bool) {
+        if self.0.focused.replace(focused) != focused {
+            self.0.listeners.notify(focused);
+        }
+    }
+}
+
+impl FocusManager for ManualFocus {
+    fn is_focused(&self) -> bool {
+        self.0.focused.get()
+    }
+
+    fn subscribe(&self, callback: Rc<dyn Fn(bool)>) -> Subscription {
+        self.0.listeners.subscribe(callback)
+    }
+}
@@ -11,7 +11,8 @@
 //! To use the library you need to provide it with a [`QueryClient`] as a context.
 //! This is ideally done in your top level component or index view so your cache
 //! is global. If you want to have separate caches for different parts of your
-//! app it could make sense to set multiple [`QueryClient`]s.
+//! app it could make sense to use multiple [`QueryClient`]s, see
+//! [Multiple Clients](#multiple-clients).
 //!
 //! ```
 //! # use sycamore::prelude::*;
@@ -44,13 +45,14 @@
 //! pub fn Hello<G: Html>(cx: Scope) -> View<G> {
 //! #   provide_context(cx, QueryClient::new(ClientOptions::default()));
 //!     let name = create_rc_signal("World".to_string());
-//!     let Query { data, status, refetch } = use_query(
+//!     let Query { data, status, refetch, .. } = use_query(
 //!         cx,
 //!         ("hello", name.get()),
 //!         move || api::hello(name.get())
 //!     );
 //!
 //!     match data.get_data() {
+//!         QueryData::Idle => view! { cx, },
 //!         QueryData::Loading => view! { cx, p { "Loading..." } },
 //!         QueryData::Ok(message) => view! { cx, p { (message) } },
 //!         QueryData::Err(err) => view! { cx, p { "An error has occured: " } p { (err) } }
@@ -63,6 +65,118 @@
 //! a mutation invalidates the query or another query with the same key fetches
 //! the data, etc.
 //!
+//! # Multiple Clients
+//!
+//! Hooks use the [`QueryClient`] from the context by default, and there can
+//! only be one of those. To keep separate caches, for example one for public
+//! data that survives logging out and one for user data that's thrown away
+//! with the session, store the clients in your own context type and pass them
+//! explicitly with [`QueryBuilder::client`](crate::query::QueryBuilder::client)
+//! and [`MutationOptions::client`].
+//!
+//! ```
+//! # use sycamore::prelude::*;
+//! # use std::rc::Rc;
+//! use sycamore_query::{
+//!     mutation::use_mutation_with_options, prelude::*, ClientOptions, MutationOptions, QueryClient,
+//! };
+//!
+//! #[derive(Clone)]
+//! struct Caches {
+//!     public: Rc<QueryClient>,
+//!     authenticated: Rc<QueryClient>,
+//! }
+//!
+//! # async fn fetch_posts() -> Result<Vec<String>, String> { Ok(vec![]) }
+//! # async fn fetch_profile() -> Result<String, String> { Ok(String::new()) }
+//! # async fn log_out(_: ()) -> Result<(), String> { Ok(()) }
+//! #[component]
+//! pub fn App<G: Html>(cx: Scope) -> View<G> {
+//!     provide_context(cx, Caches {
+//!         public: QueryClient::new(ClientOptions::default()),
+//!         authenticated: QueryClient::new(ClientOptions::default()),
+//!     });
+//!     let caches = use_context::<Caches>(cx);
+//!
+//!     let posts = QueryBuilder::new("posts", fetch_posts)
+//!         .client(caches.public.clone())
+//!         .use_query(cx);
+//!     let profile = QueryBuilder::new("profile", fetch_profile)
+//!         .client(caches.authenticated.clone())
+//!         .use_query(cx);
+//!     let logout = use_mutation_with_options(
+//!         cx,
+//!         log_out,
+//!         |_, _| {},
+//!         MutationOptions {
+//!             client: Some(caches.authenticated.clone()),
+//!             ..Default::default()
+//!         },
+//!     );
+//!     # view! { cx, }
+//! }
+//! ```
+//!
+//! # Server Side Rendering
+//!
+//! When there's no browser `window` and no native runtime feature (`tokio`
+//! or `async-std`) is enabled, or when [`ClientOptions::ssr`] is set,
+//! queries don't fetch. They serve whatever is cached and stay
+//! [`Loading`](QueryData::Loading) otherwise, so fetch the data a page needs
+//! before rendering it and put it in the cache with
+//! [`QueryClient::hydrate_query_data`].
+//!
+//! ```
+//! # use sycamore::prelude::*;
+//! # use std::time::Duration;
+//! use sycamore_query::{prelude::*, ClientOptions, QueryClient};
+//!
+//! # async fn fetch_user(id: u32) -> Result<String, String> { Ok(String::new()) }
+//! #[component]
+//! fn User<G: Html>(cx: Scope, id: u32) -> View<G> {
+//!     let user = use_query(cx, ("user", id), move || fetch_user(id));
+//!     view! { cx,
+//!         p {
+//!             (match user.data.get().as_ref() {
+//!                 QueryData::Ok(name) => name.to_string(),
+//!                 _ => "Loading...".to_string(),
+//!             })
+//!         }
+//!     }
+//! }
+//!
+//! let client = QueryClient::new(ClientOptions {
+//!     ssr: true,
+//!     ..Default::default()
+//! });
+//! client.hydrate_query_data(("user", 1), "Alice".to_string(), Duration::ZERO);
+//!
+//! let html = sycamore::render_to_string(|cx| {
+//!     provide_context(cx, client.clone());
+//!     view! { cx, User(1) User(2) }
+//! });
+//! assert!(html.contains("Alice"));
+//! assert!(html.contains("Loading..."));
+//! ```
+//!
+//! # Tracing
+//!
+//! With the `tracing` feature, query activity is reported as `tracing` spans
+//! and events at `debug` level, in addition to the
+//! [`Logger`](logger::Logger). Their names and fields are stable:
+//!
+//! * `fetch_query` span - One per fetch, including prefetches and all retries.
+//!   Fields: `key`, `attempts`, `duration_ms`, `outcome` (`"success"` or
+//!   `"error"`)
+//! * `mutation` span - One per mutation, from when it's sent. Fields: `key`
+//!   (if the mutation has a `mutation_key`), `attempts`, `duration_ms`,
+//!   `outcome`
+//! * `invalidated` event - A mounted query was invalidated. Fields: `key`
+//! * `cache_lookup` event - A query checked the cache. Fields: `key`,
+//!   `result` (`"hit"`, `"stale"` or `"miss"`)
+//!
+//! Keys are human readable if the `readable-keys` feature is enabled.
+//!
 //! # More information
 //!
 //! I don't have the time to write an entire book on this library right now, so just
@@ -73,21 +187,47 @@
 
 use std::{
     any::Any,
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    fmt::{self, Debug, Formatter},
     future::Future,
     hash::{Hash, Hasher},
     pin::Pin,
     rc::Rc,
 };
 
-use fnv::FnvHasher;
-use sycamore::reactive::{RcSignal, ReadSignal, Signal};
+use smallvec::SmallVec;
+use sycamore::reactive::{create_memo, RcSignal, ReadSignal, Scope, Signal};
 
 mod cache;
 mod client;
+/// Components for rendering queries, see
+/// [`QueryBoundary`](components::QueryBoundary)
+#[cfg(feature = "components")]
+pub mod components;
+/// Debugging tools, see [`QueryDevtools`](devtools::QueryDevtools)
+#[cfg(feature = "devtools")]
+pub mod devtools;
+/// Focus tracking, see [`FocusManager`](focus::FocusManager)
+pub mod focus;
+/// Query lifecycle logging, see [`Logger`](logger::Logger)
+pub mod logger;
 /// Mutation related functions and types
 pub mod mutation;
+/// Connectivity tracking, see [`OnlineManager`](online::OnlineManager)
+pub mod online;
 /// Query related functions and types
 pub mod query;
+/// Thread safe prefetching, see [`SyncPrefetch`](sync::SyncPrefetch)
+#[cfg(feature = "sync")]
+pub mod sync;
+/// Testing utilities, see [`TestQueryClient`](testing::TestQueryClient)
+#[cfg(feature = "test-util")]
+pub mod testing;
+/// Timers for retry delays and debouncing, see [`Timer`](timer::Timer)
+pub mod timer;
+#[cfg(feature = "tracing")]
+mod trace;
 
 /// The sycamore-query prelude.
 ///
@@ -98,95 +238,496 @@ pub mod query;
 /// use sycamore_query::prelude::*;
 /// ```
 pub mod prelude {
-    pub use crate::mutation::{use_mutation, Mutation};
-    pub use crate::query::{use_query, Query};
-    pub use crate::{keys, AsKeySignal, AsRcKeySignal, QueryData, QuerySignalExt, Status};
+    pub use crate::mutation::{use_mutation, use_mutation_simple, Mutation, MutationBuilder};
+    pub use crate::query::{use_query, use_query_fn, Query, QueryBuilder};
+    pub use crate::{
+        keys, AsKeySignal, AsRcKeySignal, DataSource, QueryData, QuerySignalExt, Status,
+    };
 }
 
 pub use client::*;
 
-pub(crate) type Fetcher =
-    Rc<dyn Fn() -> Pin<Box<dyn Future<Output = Result<Rc<dyn Any>, Rc<dyn Any>>>>>>;
-pub(crate) type DataSignal = Signal<QueryData<Rc<dyn Any>, Rc<dyn Any>>>;
+pub(crate) type FetchFuture = Pin<Box<dyn Future<Output = Result<Rc<dyn Any>, Rc<dyn Any>>>>>;
+pub(crate) type Fetcher = Rc<dyn Fn() -> FetchFuture>;
+pub(crate) type ErasedData = QueryData<Rc<dyn Any>, Rc<dyn Any>>;
+pub(crate) type DataSignal = RcSignal<ErasedData>;
+/// The data and status of a mounted query. They share a signal so a fetch
+/// that settles notifies the query's observers once, not once per field.
+pub(crate) type StateSignal = RcSignal<(ErasedData, Status)>;
+/// The hashed elements of a key. Most keys have three elements or less, which
+/// are stored inline so creating and cloning keys doesn't allocate.
+pub(crate) type KeyParts = SmallVec<[u128; 3]>;
+
+/// A hashed query key. Every element of the key is hashed separately to a
+/// 128 bit value, which allows invalidating all queries that share a prefix.
+/// You usually don't need to construct this yourself, it's created from
+/// anything that implements [`AsKeys`].
+///
+/// Hashes are stable across platforms and builds of the same version, so keys
+/// can be persisted (see [`to_bytes`](Self::to_bytes)) or, with the `serde`
+/// feature, serialized alongside cached data.
+///
+/// Prefix matching compares every element of the prefix, so a key only
+/// matches a prefix of an unrelated key family if the hashes of all of the
+/// prefix's elements collide at the same positions. Sharing some elements,
+/// or the same elements in a different order, isn't enough:
+///
+/// ```
+/// # use sycamore_query::AsKeys;
+/// let user = ("user", 1).as_keys();
+/// assert!(!user.starts_with(&("post", 1).as_keys()));
+/// assert!(!("a", "b").as_keys().starts_with(&("b", "a").as_keys()));
+/// assert!(!("a", "b").as_keys().starts_with(&"b".as_keys()));
+/// ```
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QueryKey(KeyParts);
+
+impl QueryKey {
+    /// Creates an empty key.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hashes `part` and appends it to the key as a new element.
+    ///
+    /// In debug builds, this panics if two different elements have the same
+    /// hash. That's astronomically unlikely, but would otherwise make the two
+    /// keys share their data silently.
+    pub fn push<T: Hash + ?Sized>(&mut self, part: &T) {
+        let mut hasher = Fnv128Hasher::default();
+        part.hash(&mut hasher);
+        let hash = hasher.finish128();
+        #[cfg(debug_assertions)]
+        key_collision_check(hash, part);
+        self.0.push(hash);
+    }
+
+    /// Appends all elements of `other` to the key. This is the way to build
+    /// keys with more elements than the largest supported tuple (12): split
+    /// them into tuples and join them. Prefix invalidation works across the
+    /// joined parts.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// let key = ("report", "acme", "sales", 2024, 3, "eu", "de", "berlin")
+    ///     .as_keys()
+    ///     .join(("store", 12, "weekly", true, 'a', 1u8, 2u8));
+    /// assert_eq!(key.len(), 15);
+    /// assert!(key.starts_with(&("report", "acme").as_keys()));
+    ///
+    /// // Joined keys can be used like any other key
+    /// let client = QueryClient::new(ClientOptions::default());
+    /// client.set_query_data(key.clone(), 42u32);
+    /// client.remove_queries(("report", "acme"));
+    /// assert!(client.query_data::<_, u32>(key).is_none());
+    /// ```
+    pub fn join(mut self, other: impl AsKeys) -> Self {
+        self.0.extend(other.as_keys().0);
+        self
+    }
+
+    /// The number of elements in the key.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether the key has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether the first elements of this key are equal to `prefix`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::AsKeys;
+    /// assert!(("user", 3).as_keys().starts_with(&"user".as_keys()));
+    /// assert!(!("user", 3).as_keys().starts_with(&"todos".as_keys()));
+    /// ```
+    pub fn starts_with(&self, prefix: &QueryKey) -> bool {
+        self.0.starts_with(&prefix.0)
+    }
+
+    /// The canonical byte representation of the key, 16 little endian bytes
+    /// per element. Keys are hashed the same way on every platform, so this
+    /// can be persisted or sent from a server to a client and compared
+    /// against keys created there.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::{AsKeys, QueryKey};
+    /// let key = ("user", 3usize).as_keys();
+    /// assert_eq!(QueryKey::from_bytes(&key.to_bytes()), Some(key));
+    /// // `usize` is hashed as 64 bits, so 32 bit targets agree with 64 bit ones
+    /// assert_eq!(3usize.as_keys(), 3u64.as_keys());
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.iter().flat_map(|part| part.to_le_bytes()).collect()
+    }
+
+    /// Reads a key from its canonical byte representation, as created by
+    /// [`to_bytes`](Self::to_bytes). Returns `None` if the length isn't a
+    /// multiple of 16.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if !bytes.len().is_multiple_of(16) {
+            return None;
+        }
+        let parts = bytes
+            .chunks_exact(16)
+            .map(|chunk| u128::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        Some(Self(parts))
+    }
+}
+
+/// Hashes every key element a second time with an unrelated hasher and
+/// panics if an element hashed recently has the same FNV hash but a different
+/// second hash, meaning the two elements are different but collide. Only the
+/// two hashes are remembered, 24 bytes per distinct element, and they're
+/// forgotten when a client collects garbage or clears its cache, or once
+/// [`MAX_CHECKED_ELEMENTS`] have piled up. Keys are hashed before any client
+/// sees them, so the elements can't be remembered per client.
+#[cfg(debug_assertions)]
+fn key_collision_check<T: Hash + ?Sized>(hash: u128, part: &T) {
+    use std::collections::hash_map::DefaultHasher;
+
+    let mut hasher = DefaultHasher::new();
+    part.hash(&mut hasher);
+    let check = hasher.finish();
+    CHECKED_ELEMENTS.with(|seen| {
+        let mut seen = seen.borrow_mut();
+        if seen.len() >= MAX_CHECKED_ELEMENTS {
+            seen.clear();
+        }
+        let existing = *seen.entry(hash).or_insert(check);
+        assert_eq!(
+            existing, check,
+            "query key collision: two different key elements hash to {hash:032x}. \
+             Queries using them would share data. Please report this as a bug."
+        );
+    });
+}
+
+/// How many key elements [`key_collision_check`] remembers at most, so keys
+/// built from user input like search terms can't grow it without bound.
+#[cfg(debug_assertions)]
+const MAX_CHECKED_ELEMENTS: usize = 1 << 16;
+
+#[cfg(debug_assertions)]
+thread_local! {
+    static CHECKED_ELEMENTS: std::cell::RefCell<fnv::FnvHashMap<u128, u64>> =
+        std::cell::RefCell::default();
+}
+
+/// Forget the key elements remembered by [`key_collision_check`].
+pub(crate) fn reset_key_collision_check() {
+    #[cfg(debug_assertions)]
+    CHECKED_ELEMENTS.with(|seen| seen.borrow_mut().clear());
+}
+
+/// 128 bit FNV-1a, used to hash key elements. A 64 bit hash makes collisions
+/// between unrelated keys too likely for comfort.
+struct Fnv128Hasher(u128);
+
+impl Fnv128Hasher {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    fn finish128(&self) -> u128 {
+        self.0
+    }
+}
+
+impl Default for Fnv128Hasher {
+    fn default() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+}
+
+impl Hasher for Fnv128Hasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u128;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    // Integers are always written as little endian, and `usize` as 64 bits, so
+    // a key hashes the same on every platform. This keeps keys stable between
+    // a native server and a wasm32 client.
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0 as u64
+    }
+}
 
 /// Trait for anything that can be turned into a key
 /// The reason this exists is to allow for prefix invalidation, so lists or
 /// tuples should return one hash per element.
-/// It's automatically implemented for `String`, `str` and any tuple of size
-/// 2 - 12 where each element implements `Hash`.
+/// It's automatically implemented for `String`, `str`, integers, `bool`,
+/// `char`, [`FloatKey`], slices, arrays, `Vec`s and any tuple of size 1 - 12
+/// where each element implements [`KeyPart`] (`Hash`, and `Debug` if the
+/// default `readable-keys` feature is enabled). With the `uuid` feature it's
+/// also implemented for `uuid::Uuid`.
+/// Scalar keys are single element keys, so `("todos",)` is equivalent to
+/// `"todos"` and `3u64` to `(3u64,)`.
+/// A [`QueryKey`] is its own key, so keys can be built up at runtime. For
+/// keys with more than 12 elements, join tuples with [`QueryKey::join`].
+/// Nesting a tuple inside a tuple makes the inner tuple a single element, so
+/// its parts can't be used as prefixes.
 /// If your keys aren't covered by the default implementation for some reason,
 /// you can implement this manually.
 ///
 /// # Example
 /// ```
-/// # use sycamore_query::AsKey;
-/// # use fnv::FnvHasher;
-/// # use std::hash::{Hasher, Hash};
+/// # use sycamore_query::{AsKeys, QueryKey};
 /// struct MyType {
 ///     item1: String,
 ///     item2: String,
 /// }
 ///
-/// impl AsKey for MyType {
-///     fn as_key(&self) -> Vec<u64> {
-///         let mut hash = FnvHasher::default();
-///         self.item1.hash(&mut hash);
-///         let hash1 = hash.finish();
-///         hash = FnvHasher::default();
-///         self.item2.hash(&mut hash);
-///         let hash2 = hash.finish();
-///         vec![hash1, hash2]
+/// impl AsKeys for MyType {
+///     fn as_keys(&self) -> QueryKey {
+///         let mut key = QueryKey::new();
+///         key.push(&self.item1);
+///         key.push(&self.item2);
+///         key
 ///     }
 /// }
 /// ```
-/// }
+///
+/// Single element keys are interchangeable:
+///
+/// ```
+/// # use sycamore_query::AsKeys;
+/// assert_eq!(("todos",).as_keys(), "todos".as_keys());
+/// assert_eq!((3u64,).as_keys(), 3u64.as_keys());
+/// ```
+///
+/// So are the different ways to own a string:
+///
+/// ```
+/// # use sycamore_query::AsKeys;
+/// # use std::{borrow::Cow, rc::Rc};
+/// let name: Rc<str> = Rc::from("todos");
+/// assert_eq!(name.as_keys(), "todos".as_keys());
+/// assert_eq!(Box::<str>::from("todos").as_keys(), "todos".as_keys());
+/// assert_eq!(Cow::Borrowed("todos").as_keys(), String::from("todos").as_keys());
+/// assert_eq!(("todos", name).as_keys(), ("todos", "todos").as_keys());
+/// ```
 pub trait AsKeys {
     /// Internal function to convert the type to a key for use in the query cache
     /// and notifier list.
-    fn as_keys(&self) -> Vec<u64>;
+    fn as_keys(&self) -> QueryKey;
+
+    /// A human readable representation of the key, like `("todos", 42)`.
+    /// This is only used for log output and panic messages, and is never
+    /// computed if the `readable-keys` feature is disabled. Defaults to
+    /// `None`, in which case the hashed key is shown instead.
+    fn key_repr(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Anything that can be turned into a list of keys, used by functions that
+/// operate on multiple queries like
+/// [`invalidate_queries`](QueryClient::invalidate_queries).
+///
+/// It's implemented for single keys (strings, scalars, `Option`s and tuples),
+/// [`QueryKey`], and lists of [`QueryKey`]s like the output of
+/// [`keys!`](crate::keys). Slices and `Vec`s of other types are a single key
+/// (see [`AsKeys`]), so to pass multiple keys built at runtime, collect them
+/// into a `Vec<QueryKey>`.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::*;
+/// # let client = QueryClient::new(ClientOptions::default());
+/// client.invalidate_queries(("todos", 3));
+/// client.invalidate_queries(keys!["todos", ("user", 3)]);
+///
+/// let ids = [1, 2, 3];
+/// let todos: Vec<QueryKey> = ids.iter().map(|id| ("todos", id).as_keys()).collect();
+/// client.invalidate_queries(todos);
+/// ```
+pub trait IntoKeys {
+    /// Converts the value into a list of keys.
+    fn into_keys(self) -> Vec<QueryKey>;
+}
+
+impl IntoKeys for QueryKey {
+    fn into_keys(self) -> Vec<QueryKey> {
+        vec![self]
+    }
+}
+
+impl IntoKeys for Vec<QueryKey> {
+    fn into_keys(self) -> Vec<QueryKey> {
+        self
+    }
+}
+
+impl IntoKeys for &[QueryKey] {
+    fn into_keys(self) -> Vec<QueryKey> {
+        self.to_vec()
+    }
+}
+
+impl<const N: usize> IntoKeys for [QueryKey; N] {
+    fn into_keys(self) -> Vec<QueryKey> {
+        self.into()
+    }
+}
+
+impl IntoKeys for &str {
+    fn into_keys(self) -> Vec<QueryKey> {
+        vec![self.as_keys()]
+    }
+}
+
+impl IntoKeys for String {
+    fn into_keys(self) -> Vec<QueryKey> {
+        vec![self.as_keys()]
+    }
+}
+
+impl<T: KeyPart> IntoKeys for Option<T> {
+    fn into_keys(self) -> Vec<QueryKey> {
+        vec![self.as_keys()]
+    }
+}
+
+impl AsKeys for QueryKey {
+    fn as_keys(&self) -> QueryKey {
+        self.clone()
+    }
 }
 
 impl AsKeys for str {
-    fn as_keys(&self) -> Vec<u64> {
-        let mut hash = FnvHasher::default();
-        self.hash(&mut hash);
-        vec![hash.finish()]
+    fn as_keys(&self) -> QueryKey {
+        let mut key = QueryKey::new();
+        key.push(self);
+        key
+    }
+
+    fn key_repr(&self) -> Option<String> {
+        Some(format!("{self:?}"))
     }
 }
 
 impl AsKeys for &str {
-    fn as_keys(&self) -> Vec<u64> {
-        let mut hash = FnvHasher::default();
-        self.hash(&mut hash);
-        vec![hash.finish()]
+    fn as_keys(&self) -> QueryKey {
+        (*self).as_keys()
+    }
+
+    fn key_repr(&self) -> Option<String> {
+        Some(format!("{self:?}"))
     }
 }
 
 impl AsKeys for String {
-    fn as_keys(&self) -> Vec<u64> {
+    fn as_keys(&self) -> QueryKey {
         self.as_str().as_keys()
     }
+
+    fn key_repr(&self) -> Option<String> {
+        self.as_str().key_repr()
+    }
+}
+
+macro_rules! impl_as_key_str {
+    ($($ty:ty),*) => {
+        $(
+            impl AsKeys for $ty {
+                fn as_keys(&self) -> QueryKey {
+                    str::as_keys(self)
+                }
+
+                fn key_repr(&self) -> Option<String> {
+                    str::key_repr(self)
+                }
+            }
+
+            impl IntoKeys for $ty {
+                fn into_keys(self) -> Vec<QueryKey> {
+                    vec![self.as_keys()]
+                }
+            }
+        )*
+    };
 }
 
+// Other owned strings, hashed like the `str` they contain
+impl_as_key_str!(Rc<str>, Box<str>, Cow<'_, str>);
+
+/// The bound for elements of the provided [`AsKeys`] implementations. This is
+/// `Hash`, plus `Debug` if the `readable-keys` feature is enabled. It's
+/// implemented automatically for every type satisfying those bounds.
+#[cfg(feature = "readable-keys")]
+pub trait KeyPart: Hash + Debug {}
+#[cfg(feature = "readable-keys")]
+impl<T: Hash + Debug + ?Sized> KeyPart for T {}
+
+/// The bound for elements of the provided [`AsKeys`] implementations. This is
+/// `Hash`, plus `Debug` if the `readable-keys` feature is enabled. It's
+/// implemented automatically for every type satisfying those bounds.
+#[cfg(not(feature = "readable-keys"))]
+pub trait KeyPart: Hash {}
+#[cfg(not(feature = "readable-keys"))]
+impl<T: Hash + ?Sized> KeyPart for T {}
+
 macro_rules! impl_as_key_tuple {
     ($($ty:ident),*) => {
-        impl<$($ty: Hash),*> AsKeys for ($($ty),*) {
-            fn as_keys(&self) -> Vec<u64> {
+        impl<$($ty: KeyPart),*> AsKeys for ($($ty,)*) {
+            fn as_keys(&self) -> QueryKey {
                 #[allow(non_snake_case)]
-                let ($($ty),*) = self;
-                vec![$(
-                    {
-                        let mut hash = FnvHasher::default();
-                        $ty.hash(&mut hash);
-                        hash.finish()
-                    }
-                ),*]
+                let ($($ty,)*) = self;
+                let mut key = QueryKey::new();
+                $(key.push($ty);)*
+                key
+            }
+
+            #[cfg(feature = "readable-keys")]
+            fn key_repr(&self) -> Option<String> {
+                Some(format!("{self:?}"))
+            }
+        }
+
+        impl<$($ty: KeyPart),*> IntoKeys for ($($ty,)*) {
+            fn into_keys(self) -> Vec<QueryKey> {
+                vec![self.as_keys()]
             }
         }
     };
 }
 
 // Implement for tuples up to 12 long
+impl_as_key_tuple!(T1);
 impl_as_key_tuple!(T1, T2);
 impl_as_key_tuple!(T1, T2, T3);
 impl_as_key_tuple!(T1, T2, T3, T4);
@@ -199,21 +740,360 @@ impl_as_key_tuple!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
 impl_as_key_tuple!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
 impl_as_key_tuple!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12);
 
+macro_rules! impl_as_key_scalar {
+    ($($ty:ty),*) => {
+        $(
+            impl AsKeys for $ty {
+                fn as_keys(&self) -> QueryKey {
+                    let mut key = QueryKey::new();
+                    key.push(self);
+                    key
+                }
+
+                fn key_repr(&self) -> Option<String> {
+                    Some(format!("{self:?}"))
+                }
+            }
+
+            impl IntoKeys for $ty {
+                fn into_keys(self) -> Vec<QueryKey> {
+                    vec![self.as_keys()]
+                }
+            }
+        )*
+    };
+}
+
+impl_as_key_scalar!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, bool, char);
+#[cfg(feature = "uuid")]
+impl_as_key_scalar!(uuid::Uuid);
+impl_as_key_scalar!(FloatKey);
+
+/// A float that can be used in keys. Floats don't implement `Hash`, so wrap
+/// them in this to use them in key tuples. The float is hashed by its bit
+/// pattern, except that all `NaN`s are treated as equal, and `-0.0` as equal
+/// to `0.0`. `f32`s are converted to `f64` losslessly.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::{AsKeys, FloatKey};
+/// let (lat, lng) = (52.52, 13.405);
+/// let key = ("map", FloatKey(lat), FloatKey(lng)).as_keys();
+/// assert!(key.starts_with(&"map".as_keys()));
+///
+/// assert_eq!(FloatKey(0.0).as_keys(), FloatKey(-0.0).as_keys());
+/// assert_eq!(FloatKey(f64::NAN).as_keys(), FloatKey(-f64::NAN).as_keys());
+/// assert_eq!(FloatKey::from(1.5f32).as_keys(), FloatKey(1.5).as_keys());
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct FloatKey(pub f64);
+
+impl FloatKey {
+    fn canonical_bits(&self) -> u64 {
+        if self.0.is_nan() {
+            f64::NAN.to_bits()
+        } else if self.0 == 0.0 {
+            0
+        } else {
+            self.0.to_bits()
+        }
+    }
+}
+
+impl Hash for FloatKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical_bits().hash(state);
+    }
+}
+
+impl PartialEq for FloatKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical_bits() == other.canonical_bits()
+    }
+}
+
+impl Eq for FloatKey {}
+
+impl From<f64> for FloatKey {
+    fn from(value: f64) -> Self {
+        Self(value)
+    }
+}
+
+impl From<f32> for FloatKey {
+    fn from(value: f32) -> Self {
+        Self(value.into())
+    }
+}
+
+/// An optional key is a single key element. `None` is hashed to a distinct
+/// sentinel, and `Some(x)` is tagged so it never matches the bare `x`.
+///
+/// Optional parts in tuples like `("users", Some(team_id))` and
+/// `("users", None)` are regular elements as well, so the prefix `"users"`
+/// invalidates both variants.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::{AsKeys, QueryKey};
+/// assert_ne!(Some("x").as_keys(), None::<&str>.as_keys());
+/// assert_ne!(Some("x").as_keys(), "x".as_keys());
+///
+/// let mut zero = QueryKey::new();
+/// zero.push(&0i64);
+/// assert_ne!(Some(0i64).as_keys(), zero);
+/// assert_ne!(None::<i64>.as_keys(), zero);
+///
+/// let users = "users".as_keys();
+/// assert!(("users", Some(3)).as_keys().starts_with(&users));
+/// assert!(("users", None::<i32>).as_keys().starts_with(&users));
+/// ```
+impl<T: KeyPart> AsKeys for Option<T> {
+    fn as_keys(&self) -> QueryKey {
+        let mut key = QueryKey::new();
+        match self {
+            Some(value) => key.push(&("sycamore_query::Some", value)),
+            None => key.push("sycamore_query::None"),
+        }
+        key
+    }
+
+    #[cfg(feature = "readable-keys")]
+    fn key_repr(&self) -> Option<String> {
+        Some(format!("{self:?}"))
+    }
+}
+
+/// Each element of a slice is its own key element, so `["posts", "rust"]`
+/// will be invalidated by the prefix `["posts"]` (or `"posts"`). Order matters:
+/// `[1, 2]` and `[2, 1]` are different keys.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::AsKeys;
+/// let tag_ids = vec![1, 2];
+/// assert_ne!(tag_ids.as_keys(), [2, 1].as_keys());
+/// assert!(tag_ids.as_keys().starts_with(&[1].as_keys()));
+/// ```
+impl<T: KeyPart> AsKeys for [T] {
+    fn as_keys(&self) -> QueryKey {
+        let mut key = QueryKey::new();
+        for part in self {
+            key.push(part);
+        }
+        key
+    }
+
+    #[cfg(feature = "readable-keys")]
+    fn key_repr(&self) -> Option<String> {
+        Some(format!("{self:?}"))
+    }
+}
+
+/// See the implementation for `[T]`.
+impl<T: KeyPart> AsKeys for &[T] {
+    fn as_keys(&self) -> QueryKey {
+        (*self).as_keys()
+    }
+
+    fn key_repr(&self) -> Option<String> {
+        (*self).key_repr()
+    }
+}
+
+/// See the implementation for `[T]`.
+impl<T: KeyPart, const N: usize> AsKeys for [T; N] {
+    fn as_keys(&self) -> QueryKey {
+        self.as_slice().as_keys()
+    }
+
+    fn key_repr(&self) -> Option<String> {
+        self.as_slice().key_repr()
+    }
+}
+
+/// See the implementation for `[T]`.
+impl<T: KeyPart> AsKeys for Vec<T> {
+    fn as_keys(&self) -> QueryKey {
+        self.as_slice().as_keys()
+    }
+
+    fn key_repr(&self) -> Option<String> {
+        self.as_slice().key_repr()
+    }
+}
+
+/// A list of keys of different types. The elements of each key are appended
+/// in order, so `vec![Box::new("posts"), Box::new(("rust", 2))]` is
+/// equivalent to `("posts", "rust", 2)`.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::AsKeys;
+/// let key: Vec<Box<dyn AsKeys>> = vec![Box::new("posts"), Box::new(("rust", 2))];
+/// assert_eq!(key.as_keys(), ("posts", "rust", 2).as_keys());
+/// ```
+impl AsKeys for Vec<Box<dyn AsKeys>> {
+    fn as_keys(&self) -> QueryKey {
+        let mut key = QueryKey::new();
+        for part in self {
+            key.0.extend(part.as_keys().0);
+        }
+        key
+    }
+
+    fn key_repr(&self) -> Option<String> {
+        let parts = self
+            .iter()
+            .map(|part| {
+                part.key_repr()
+                    .unwrap_or_else(|| format!("{:?}", part.as_keys()))
+            })
+            .collect::<Vec<_>>();
+        Some(format!("[{}]", parts.join(", ")))
+    }
+}
+
+/// A subscription to events, for example from a
+/// [`FocusManager`](focus::FocusManager). Unsubscribes when dropped.
+#[must_use = "the subscription ends when it's dropped"]
+pub struct Subscription(Option<Box<dyn FnOnce()>>);
+
+impl Subscription {
+    /// A subscription that runs `unsubscribe` when it's dropped.
+    pub fn new(unsubscribe: impl FnOnce() + 'static) -> Self {
+        Self(Some(Box::new(unsubscribe)))
+    }
+
+    /// A subscription that doesn't need any cleanup, for sources that never
+    /// emit events.
+    pub fn none() -> Self {
+        Self(None)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(unsubscribe) = self.0.take() {
+            unsubscribe();
+        }
+    }
+}
+
+type Listener<T> = Rc<dyn Fn(T)>;
+
+/// Callbacks subscribed to a value, like focus, connectivity or query events.
+pub(crate) struct Listeners<T = bool> {
+    next_id: Cell<u64>,
+    listeners: RefCell<Vec<(u64, Listener<T>)>>,
+}
+
+impl<T> Default for Listeners<T> {
+    fn default() -> Self {
+        Self {
+            next_id: Cell::new(0),
+            listeners: RefCell::default(),
+        }
+    }
+}
+
+impl<T: Clone + 'static> Listeners<T> {
+    /// Call `callback` on every [`notify`](Listeners::notify) until the
+    /// subscription is dropped.
+    pub(crate) fn subscribe(self: &Rc<Self>, callback: Listener<T>) -> Subscription {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.listeners.borrow_mut().push((id, callback));
+        let listeners = Rc::downgrade(self);
+        Subscription::new(move || {
+            if let Some(listeners) = listeners.upgrade() {
+                listeners
+                    .listeners
+                    .borrow_mut()
+                    .retain(|(listener, _)| *listener != id);
+            }
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.listeners.borrow().is_empty()
+    }
+
+    pub(crate) fn notify(&self, value: T) {
+        // Collected first so callbacks can subscribe or unsubscribe
+        let listeners: Vec<_> = self
+            .listeners
+            .borrow()
+            .iter()
+            .map(|(_, listener)| listener.clone())
+            .collect();
+        for listener in listeners {
+            listener(value.clone());
+        }
+    }
+}
+
 /// The data type of a query.
 ///
 /// # States
 ///
 /// * `Loading` - No query data is available yet
+/// * `Idle` - No query data is available and none is being fetched because the
+///   query is disabled.
 /// * `Ok` - Query data was successfully fetched and is available. Note this
-/// might be stale data, check `QueryStatus` if you need to verify whether the
-/// query is currently fetching fresh data.
+///   might be stale data, check `QueryStatus` if you need to verify whether the
+///   query is currently fetching fresh data.
 /// * `Err` - Query data still wasn't able to be fetched after the retry strategy
-/// was exhausted. This contains the backing error.
+///   was exhausted. This contains the backing error.
+///
+/// # Matching
+///
+/// A disabled query (see [`QueryBuilder::enabled`](crate::query::QueryBuilder::enabled))
+/// with no data is `Idle` rather than `Loading`, so it doesn't show a loading
+/// indicator. Usually you want to render nothing or a placeholder for it:
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::QueryData;
+/// # fn render<G: Html>(cx: Scope, data: QueryData<String, String>) -> View<G> {
+/// match data {
+///     QueryData::Idle => view! { cx, },
+///     QueryData::Loading => view! { cx, p { "Loading..." } },
+///     QueryData::Ok(message) => view! { cx, p { (message) } },
+///     QueryData::Err(err) => view! { cx, p { "An error has occured: " (err) } },
+/// }
+/// # }
+/// ```
+///
+/// # Comparing
+///
+/// `QueryData` implements `Debug`, `PartialEq` and `Eq` if the data and error
+/// types do, and `Serialize`/`Deserialize` with the `serde` feature.
 ///
-#[derive(Clone)]
+/// ```
+/// # use sycamore_query::QueryData;
+/// assert_eq!(QueryData::<u32, String>::Loading, QueryData::Loading);
+/// assert_ne!(QueryData::<u32, String>::Loading, QueryData::Idle);
+/// assert_eq!(QueryData::<_, String>::Ok(3), QueryData::Ok(3));
+/// assert_ne!(QueryData::<_, String>::Ok(3), QueryData::Ok(4));
+/// assert_eq!(QueryData::<u32, _>::Err("failed"), QueryData::Err("failed"));
+/// assert_eq!(format!("{:?}", QueryData::<_, String>::Ok(3)), "Ok(3)");
+/// assert_eq!(format!("{:?}", QueryData::<u32, _>::Err("failed")), "Err(\"failed\")");
+/// assert_eq!(format!("{:?}", QueryData::<u32, String>::Loading), "Loading");
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum QueryData<T, E> {
     /// No query data is available yet
     Loading,
+    /// No query data is available and none is being fetched because the
+    /// query is disabled.
+    Idle,
     /// Query data was successfully fetched and is available. Note this
     /// might be stale data, check `QueryStatus` if you need to verify whether the
     /// query is currently fetching fresh data.
@@ -223,16 +1103,257 @@ pub enum QueryData<T, E> {
     Err(E),
 }
 
+impl<T, E> QueryData<T, E> {
+    /// Returns `true` if the data is [`QueryData::Loading`].
+    pub fn is_loading(&self) -> bool {
+        matches!(self, QueryData::Loading)
+    }
+
+    /// Returns `true` if the data is [`QueryData::Idle`].
+    pub fn is_idle(&self) -> bool {
+        matches!(self, QueryData::Idle)
+    }
+
+    /// Returns `true` if the data is [`QueryData::Ok`].
+    pub fn is_ok(&self) -> bool {
+        matches!(self, QueryData::Ok(_))
+    }
+
+    /// Returns `true` if the data is [`QueryData::Err`].
+    pub fn is_err(&self) -> bool {
+        matches!(self, QueryData::Err(_))
+    }
+
+    /// Converts to an `Option` of the data, discarding any error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::QueryData;
+    /// # use std::rc::Rc;
+    /// struct User {
+    ///     name: String,
+    /// }
+    ///
+    /// let data = QueryData::<_, Rc<String>>::Ok(Rc::new(User { name: "Ferris".to_string() }));
+    /// assert_eq!(data.ok().map(|user| user.name.clone()), Some("Ferris".to_string()));
+    /// ```
+    pub fn ok(self) -> Option<T> {
+        match self {
+            QueryData::Ok(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Converts to an `Option` of the error, discarding any data.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::QueryData;
+    /// assert_eq!(QueryData::<u32, _>::Err("failed").err(), Some("failed"));
+    /// assert_eq!(QueryData::<u32, &str>::Loading.err(), None);
+    /// ```
+    pub fn err(self) -> Option<E> {
+        match self {
+            QueryData::Err(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Converts from `&QueryData<T, E>` to `QueryData<&T, &E>`.
+    pub fn as_ref(&self) -> QueryData<&T, &E> {
+        match self {
+            QueryData::Loading => QueryData::Loading,
+            QueryData::Idle => QueryData::Idle,
+            QueryData::Ok(data) => QueryData::Ok(data),
+            QueryData::Err(err) => QueryData::Err(err),
+        }
+    }
+
+    /// Returns a `Result` of references to the data or error, or `None` if no
+    /// data is available yet.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::QueryData;
+    /// let data = QueryData::<u32, String>::Ok(3);
+    /// assert_eq!(data.as_result(), Some(Ok(&3)));
+    /// assert_eq!(QueryData::<u32, String>::Idle.as_result(), None);
+    /// ```
+    pub fn as_result(&self) -> Option<Result<&T, &E>> {
+        match self {
+            QueryData::Loading | QueryData::Idle => None,
+            QueryData::Ok(data) => Some(Ok(data)),
+            QueryData::Err(err) => Some(Err(err)),
+        }
+    }
+
+    /// Converts to a `Result`, or `None` if no data is available yet. This is
+    /// the same as `Option::<Result<T, E>>::from(data)`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::QueryData;
+    /// fn parse(data: Result<u32, String>) -> Result<String, String> {
+    ///     data.map(|n| n.to_string())
+    /// }
+    ///
+    /// let data = QueryData::Ok(3);
+    /// assert_eq!(data.into_result().map(parse), Some(Ok("3".to_string())));
+    /// ```
+    pub fn into_result(self) -> Option<Result<T, E>> {
+        match self {
+            QueryData::Loading | QueryData::Idle => None,
+            QueryData::Ok(data) => Some(Ok(data)),
+            QueryData::Err(err) => Some(Err(err)),
+        }
+    }
+
+    /// Maps the data with `f`, leaving all other states untouched.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::QueryData;
+    /// let data = QueryData::<_, String>::Ok(vec![1, 2, 3]);
+    /// assert_eq!(data.map(|todos| todos.len()).ok(), Some(3));
+    ///
+    /// let data = QueryData::<Vec<u32>, String>::Loading;
+    /// assert!(data.map(|todos| todos.len()).is_loading());
+    /// ```
+    ///
+    /// Together with [`get_data`](QuerySignalExt::get_data) this makes
+    /// derived views short:
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::prelude::*;
+    /// # use std::rc::Rc;
+    /// create_scope_immediate(|cx| {
+    ///     let data = create_signal(cx, QueryData::<Rc<f64>, Rc<String>>::Ok(Rc::new(3.14159)));
+    ///     let label = create_memo(cx, || data.get_data().map(|n| format!("{n:.2}")));
+    ///     assert_eq!(label.get().as_ref(), &QueryData::Ok("3.14".to_string()));
+    /// });
+    /// ```
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> QueryData<U, E> {
+        match self {
+            QueryData::Loading => QueryData::Loading,
+            QueryData::Idle => QueryData::Idle,
+            QueryData::Ok(data) => QueryData::Ok(f(data)),
+            QueryData::Err(err) => QueryData::Err(err),
+        }
+    }
+
+    /// Maps the error with `f`, leaving all other states untouched.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::QueryData;
+    /// let data = QueryData::<u32, _>::Err(404);
+    /// assert_eq!(data.map_err(|code| format!("HTTP {code}")).err(), Some("HTTP 404".to_string()));
+    /// ```
+    pub fn map_err<F>(self, f: impl FnOnce(E) -> F) -> QueryData<T, F> {
+        match self {
+            QueryData::Loading => QueryData::Loading,
+            QueryData::Idle => QueryData::Idle,
+            QueryData::Ok(data) => QueryData::Ok(data),
+            QueryData::Err(err) => QueryData::Err(f(err)),
+        }
+    }
+
+    /// Returns the data, or `default` if there is no data.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::QueryData;
+    /// assert_eq!(QueryData::<_, String>::Ok(3).unwrap_or(0), 3);
+    /// assert_eq!(QueryData::<_, String>::Loading.unwrap_or(0), 0);
+    /// ```
+    pub fn unwrap_or(self, default: T) -> T {
+        self.ok().unwrap_or(default)
+    }
+
+    /// Returns the data, or computes a value with `f` if there is no data.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::QueryData;
+    /// let data = QueryData::<Vec<u32>, String>::Err("failed".to_string());
+    /// assert_eq!(data.unwrap_or_else(Vec::new), Vec::<u32>::new());
+    /// ```
+    pub fn unwrap_or_else(self, f: impl FnOnce() -> T) -> T {
+        self.ok().unwrap_or_else(f)
+    }
+
+    /// Returns the data, or `T::default()` if there is no data.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::QueryData;
+    /// assert_eq!(QueryData::<String, String>::Idle.unwrap_or_default(), "");
+    /// assert_eq!(QueryData::<u32, String>::Err("failed".to_string()).unwrap_or_default(), 0);
+    /// ```
+    pub fn unwrap_or_default(self) -> T
+    where
+        T: Default,
+    {
+        self.ok().unwrap_or_default()
+    }
+}
+
+/// Converts a `Result` into [`QueryData::Ok`] or [`QueryData::Err`].
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::QueryData;
+/// let data: QueryData<u32, String> = Ok(3).into();
+/// assert_eq!(data.ok(), Some(3));
+/// ```
+impl<T, E> From<Result<T, E>> for QueryData<T, E> {
+    fn from(result: Result<T, E>) -> Self {
+        match result {
+            Ok(data) => QueryData::Ok(data),
+            Err(err) => QueryData::Err(err),
+        }
+    }
+}
+
+/// Converts an optional `Result` into [`QueryData`], where `None` is
+/// [`QueryData::Loading`].
+impl<T, E> From<Option<Result<T, E>>> for QueryData<T, E> {
+    fn from(result: Option<Result<T, E>>) -> Self {
+        result.map_or(QueryData::Loading, QueryData::from)
+    }
+}
+
+/// See [`QueryData::into_result`].
+impl<T, E> From<QueryData<T, E>> for Option<Result<T, E>> {
+    fn from(data: QueryData<T, E>) -> Self {
+        data.into_result()
+    }
+}
+
 /// The status of a query.
 ///
 /// # States
 ///
 /// * `Fetching` - Query data is currently being fetched. This might be because
-/// no data is available ([`QueryData::Loading`]) or because the data is
-/// considered stale.
+///   no data is available ([`QueryData::Loading`]) or because the data is
+///   considered stale.
 /// * `Success` - Query data is available and fresh.
 /// * `Idle` - Query is disabled from running.
+/// * `Paused` - A mutation was started while the client is offline. It will
+///   run once the client is back online.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Status {
     /// Query data is currently being fetched. This might be because
     /// no data is available ([`QueryData::Loading`]) or because the data is
@@ -241,6 +1362,56 @@ pub enum Status {
     Success,
     /// Query is disabled from running.
     Idle,
+    /// A mutation was started while the client is offline. It will run once
+    /// the client is back online.
+    Paused,
+}
+
+/// Where the data of a query came from. Useful for measuring cache hit
+/// rates or debugging.
+///
+/// # Sources
+///
+/// * `None` - The query has no data yet.
+/// * `Cache` - The data was already in the cache when the query was used.
+/// * `Fetch` - The data was fetched by the query's fetcher in this session.
+/// * `Manual` - The data was set with [`QueryClient::set_query_data`] or
+///   [`QueryClient::update_queries`].
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, ClientOptions, QueryClient};
+/// # use std::time::Duration;
+/// create_scope_immediate(|cx| {
+///     let client = QueryClient::new(ClientOptions {
+///         stale_time: Duration::from_secs(60),
+///         ..ClientOptions::default()
+///     });
+///     provide_context(cx, client.clone());
+///     client.set_query_data("user", "Ferris".to_string());
+///
+///     let user = use_query(cx, "user", || async { Ok::<_, ()>("Ferris".to_string()) });
+///     assert_eq!(*user.data_source.get(), DataSource::Cache);
+///
+///     client.set_query_data("user", "Corro".to_string());
+///     assert_eq!(*user.data_source.get(), DataSource::Manual);
+/// });
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DataSource {
+    /// The query has no data yet.
+    #[default]
+    None,
+    /// The data was already in the cache when the query was used.
+    Cache,
+    /// The data was fetched by the query's fetcher in this session.
+    Fetch,
+    /// The data was set with [`QueryClient::set_query_data`] or
+    /// [`QueryClient::update_queries`].
+    Manual,
 }
 
 /// A convenience macro for passing a set of keys.
@@ -274,6 +1445,112 @@ macro_rules! keys {
     };
 }
 
+/// Defines a reusable, typed query. The key, fetcher and types are declared
+/// once, so components can't accidentally use the same key with a different
+/// type.
+///
+/// ```text
+/// define_query! {
+///     /// Optional docs for the generated type
+///     pub Name(arg1: Type1, arg2: Type2) -> Result<Data, Error> = fetcher;
+/// }
+/// ```
+///
+/// generates a unit struct `Name` with these associated functions:
+///
+/// * `key(arg1, arg2)` - The key of the query, `("Name", arg1, arg2)`.
+/// * `use_query(cx, arg1, arg2)` - Uses the query, see
+///   [`use_query`](crate::query::use_query).
+/// * `invalidate(client, arg1, arg2)` - Invalidates the query, see
+///   [`QueryClient::invalidate_queries`].
+/// * `prefetch(client, arg1, arg2)` - Prefetches the query, see
+///   [`QueryClient::prefetch_query`].
+///
+/// `fetcher` is called with the arguments and must return a future resolving
+/// to `Result<Data, Error>`. Arguments need to implement `Clone` and
+/// [`KeyPart`], and be `'static`. Up to 11 arguments are supported.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, define_query, AsKeys, ClientOptions, QueryClient};
+/// # #[derive(Clone)] pub struct User;
+/// # pub struct ApiError;
+/// async fn fetch_user(id: u64) -> Result<User, ApiError> {
+///     // ...
+/// #   Ok(User)
+/// }
+///
+/// define_query! {
+///     /// A user by ID.
+///     pub UserQuery(id: u64) -> Result<User, ApiError> = fetch_user;
+/// }
+///
+/// #[component]
+/// fn Profile<G: Html>(cx: Scope) -> View<G> {
+/// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+///     let Query { data, .. } = UserQuery::use_query(cx, 3);
+///     view! { cx, }
+/// }
+///
+/// assert_eq!(UserQuery::key(3).as_keys(), ("UserQuery", 3u64).as_keys());
+/// ```
+#[macro_export]
+macro_rules! define_query {
+    (
+        $(#[$attr:meta])*
+        $vis:vis $name:ident($($arg:ident: $arg_ty:ty),* $(,)?) -> Result<$data:ty, $err:ty> = $fetcher:expr;
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, Default)]
+        $vis struct $name;
+
+        #[allow(dead_code)]
+        impl $name {
+            /// The key of the query.
+            $vis fn key($($arg: $arg_ty),*) -> (&'static str, $($arg_ty,)*) {
+                (stringify!($name), $($arg,)*)
+            }
+
+            /// Use the query.
+            $vis fn use_query<'a>(
+                cx: $crate::__private::Scope<'a>,
+                $($arg: $arg_ty),*
+            ) -> $crate::query::Query<'a, $data, $err, impl Fn() + 'a> {
+                let key = Self::key($(::std::clone::Clone::clone(&$arg)),*);
+                $crate::query::use_query(cx, key, move || {
+                    ($fetcher)($(::std::clone::Clone::clone(&$arg)),*)
+                })
+            }
+
+            /// Invalidate the query.
+            $vis fn invalidate(
+                client: &::std::rc::Rc<$crate::QueryClient>,
+                $($arg: $arg_ty),*
+            ) {
+                client.invalidate_queries(Self::key($($arg),*));
+            }
+
+            /// Fetch the query ahead of time.
+            $vis fn prefetch(
+                client: &::std::rc::Rc<$crate::QueryClient>,
+                $($arg: $arg_ty),*
+            ) {
+                let key = Self::key($(::std::clone::Clone::clone(&$arg)),*);
+                client.prefetch_query(key, move || {
+                    ($fetcher)($(::std::clone::Clone::clone(&$arg)),*)
+                });
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+pub mod __private {
+    pub use sycamore::reactive::Scope;
+}
+
 /// Utility functions for dealing with QueryData in signals.
 pub trait QuerySignalExt<T, E> {
     /// Unwraps the outer `Rc` of the signal to provide you with an easier to
@@ -290,37 +1567,107 @@ pub trait QuerySignalExt<T, E> {
     /// match signal.get_data() {
     ///     QueryData::Ok(message) => println!("{message}"),
     ///     QueryData::Err(err) => eprintln!("{err}"),
-    ///     QueryData::Loading => println!("No data yet")
+    ///     QueryData::Loading => println!("No data yet"),
+    ///     QueryData::Idle => println!("Query is disabled")
     /// }
     ///
     /// ```
     fn get_data(&self) -> QueryData<Rc<T>, Rc<E>>;
+
+    /// The query data, or `None` if there is none.
+    ///
+    /// # Example Usage
+    ///
+    /// ```
+    /// # use sycamore_query::{QueryData, QuerySignalExt};
+    /// # use sycamore::reactive::create_rc_signal;
+    /// # use std::rc::Rc;
+    /// let signal = create_rc_signal::<QueryData<_, Rc<String>>>(QueryData::Ok(Rc::new(3)));
+    /// assert_eq!(signal.ok_data(), Some(Rc::new(3)));
+    /// ```
+    fn ok_data(&self) -> Option<Rc<T>> {
+        self.get_data().ok()
+    }
+
+    /// The query error, or `None` if there is none.
+    ///
+    /// # Example Usage
+    ///
+    /// ```
+    /// # use sycamore_query::{QueryData, QuerySignalExt};
+    /// # use sycamore::reactive::create_rc_signal;
+    /// # use std::rc::Rc;
+    /// let signal = create_rc_signal::<QueryData<Rc<u32>, _>>(QueryData::Err(Rc::new("failed")));
+    /// assert_eq!(signal.err_data(), Some(Rc::new("failed")));
+    /// ```
+    fn err_data(&self) -> Option<Rc<E>> {
+        self.get_data().err()
+    }
+
+    /// Creates a memo of `f` applied to the query data, or `None` if there is
+    /// no data. The memo only updates when the query data changes.
+    ///
+    /// # Example Usage
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{QueryData, QuerySignalExt};
+    /// # use std::rc::Rc;
+    /// struct User {
+    ///     name: String,
+    /// }
+    ///
+    /// create_scope_immediate(|cx| {
+    ///     let data = create_signal(cx, QueryData::<_, Rc<String>>::Loading);
+    ///     let name = data.map_data(cx, |user: &User| user.name.clone());
+    ///     assert_eq!(*name.get(), None);
+    ///
+    ///     data.set(QueryData::Ok(Rc::new(User { name: "Ferris".to_string() })));
+    ///     assert_eq!(*name.get(), Some("Ferris".to_string()));
+    /// });
+    /// ```
+    fn map_data<'a, U: 'a>(
+        &'a self,
+        cx: Scope<'a>,
+        f: impl Fn(&T) -> U + 'a,
+    ) -> &'a ReadSignal<Option<U>> {
+        create_memo(cx, move || self.ok_data().map(|data| f(&data)))
+    }
 }
 
 impl<T, E> QuerySignalExt<T, E> for ReadSignal<QueryData<Rc<T>, Rc<E>>> {
     fn get_data(&self) -> QueryData<Rc<T>, Rc<E>> {
         match self.get().as_ref() {
             QueryData::Loading => QueryData::Loading,
+            QueryData::Idle => QueryData::Idle,
             QueryData::Ok(data) => QueryData::Ok(data.clone()),
             QueryData::Err(err) => QueryData::Err(err.clone()),
         }
     }
 }
 
-struct MyRcSignal<T>(Rc<Signal<T>>);
+impl<T, E> QuerySignalExt<T, E> for RcSignal<QueryData<Rc<T>, Rc<E>>> {
+    fn get_data(&self) -> QueryData<Rc<T>, Rc<E>> {
+        (***self).get_data()
+    }
+}
 
-pub(crate) fn as_rc<T>(signal: RcSignal<T>) -> Rc<Signal<T>> {
-    // UNSAFE: This is actually kind of unsafe, but as long as the signature of
-    // `RcSignal` doesn't change and the compiler doesn't throw a curveball it
-    // should work. This should be replaced with a builtin way to do it.
-    let signal: MyRcSignal<T> = unsafe { std::mem::transmute(signal) };
-    signal.0
+impl<T, E> QuerySignalExt<T, E> for Rc<Signal<QueryData<Rc<T>, Rc<E>>>> {
+    fn get_data(&self) -> QueryData<Rc<T>, Rc<E>> {
+        (***self).get_data()
+    }
 }
 
 /// Internal type for tracking key changes. Only exposed because it's used in a public trait
 pub struct KeySignal<'cx, T: Hash>(&'cx ReadSignal<T>);
 /// Internal type for tracking key changes. Only exposed because it's used in a public trait
 pub struct RcKeySignal<T: Hash>(RcSignal<T>);
+/// Internal type for tracking changes to a projection of a signal. Only exposed because it's used
+/// in a public trait
+pub struct MappedKeySignal<'cx, T, F>(&'cx ReadSignal<T>, F);
+/// Internal type for tracking changes to a projection of a signal. Only exposed because it's used
+/// in a public trait
+pub struct RcMappedKeySignal<T, F>(RcSignal<T>, F);
 
 /// Extension to allow for tracking key changes. If I can get some changes into sycamore this should
 /// become redundant
@@ -330,21 +1677,64 @@ pub struct RcKeySignal<T: Hash>(RcSignal<T>);
 /// ```
 /// # use sycamore::prelude::*;
 /// use sycamore_query::prelude::*;
+/// # use sycamore_query::{QueryClient, ClientOptions};
 /// # #[component]
 /// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+/// # provide_context(cx, QueryClient::new(ClientOptions::default()));
 /// # async fn hello(s: String) -> Result<String, String> {
 /// #   Ok(s.to_string())
 /// # }
-///  let signal = create_signal(cx, "Test");
+/// let signal = create_signal(cx, "Test");
 /// // Updates every time signal changes
-/// use_query(cx, ("hello", signal.key()), move || hello(signal.get().to_string());
+/// use_query(cx, ("hello", signal.key()), move || hello("Test".to_string()));
+/// # view! { cx, }
 /// # }
 /// ```
-pub trait AsKeySignal<T: Hash> {
+pub trait AsKeySignal<T> {
     /// Creates a reference to the signal that tracks when it's hashed (sycamore uses
     /// [`get_untracked`](sycamore::reactive::ReadSignal) in the [`Hash`](std::hash::Hash)
     /// implementation for signals).
-    fn key(&self) -> KeySignal<'_, T>;
+    fn key(&self) -> KeySignal<'_, T>
+    where
+        T: Hash;
+
+    /// Like [`key`](Self::key), but only hashes the part of the value returned by `f`. The
+    /// signal is still tracked as a whole, but since the query only cares about the hashed key,
+    /// changes that don't affect the projection won't cause a refetch. The signal's type doesn't
+    /// need to implement `Hash`.
+    ///
+    /// This is a single key element, so it composes with tuples like any other value:
+    /// `("todos", filters.map_key(|f| f.category.clone()))` is invalidated by the prefix
+    /// `"todos"`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, AsKeys};
+    /// #[derive(Clone)]
+    /// struct Filters {
+    ///     category: String,
+    ///     sort_ascending: bool,
+    /// }
+    ///
+    /// create_scope_immediate(|cx| {
+    ///     let filters = create_signal(cx, Filters {
+    ///         category: "books".to_string(),
+    ///         sort_ascending: true,
+    ///     });
+    ///     let key = || ("todos", filters.map_key(|f| f.category.clone())).as_keys();
+    ///     let initial = key();
+    ///
+    ///     // Client-side only, doesn't change the key
+    ///     filters.modify().sort_ascending = false;
+    ///     assert_eq!(key(), initial);
+    ///
+    ///     filters.modify().category = "music".to_string();
+    ///     assert_ne!(key(), initial);
+    /// });
+    /// ```
+    fn map_key<U: Hash, F: Fn(&T) -> U>(&self, f: F) -> MappedKeySignal<'_, T, F>;
 }
 
 /// Extension to allow for tracking key changes. If I can get some changes into sycamore this should
@@ -355,31 +1745,55 @@ pub trait AsKeySignal<T: Hash> {
 /// ```
 /// # use sycamore::prelude::*;
 /// use sycamore_query::prelude::*;
+/// # use sycamore_query::{QueryClient, ClientOptions};
 /// # #[component]
 /// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+/// # provide_context(cx, QueryClient::new(ClientOptions::default()));
 /// # async fn hello(s: String) -> Result<String, String> {
 /// #   Ok(s.to_string())
 /// # }
 /// let signal = create_rc_signal("Test");
 /// // Updates every time signal changes
-/// use_query(cx, ("hello", signal.clone().rc_key()), move || hello(signal.get().to_string());
+/// use_query(cx, ("hello", signal.rc_key()), move || hello(signal.get().to_string()));
+/// # view! { cx, }
 /// # }
 /// ```
-pub trait AsRcKeySignal<T: Hash> {
+pub trait AsRcKeySignal<T> {
     /// Creates a copy of the signal that tracks when it's hashed (sycamore uses `get_untracked`
-    /// in the `Hash` implementation for signals).
-    fn rc_key(self) -> RcKeySignal<T>;
+    /// in the `Hash` implementation for signals). The signal is cloned, so it can still be used
+    /// afterwards, for example in the fetcher.
+    fn rc_key(&self) -> RcKeySignal<T>
+    where
+        T: Hash;
+
+    /// Like [`rc_key`](Self::rc_key), but only hashes the part of the value returned by `f`.
+    /// See [`AsKeySignal::map_key`] for details.
+    fn map_rc_key<U: Hash, F: Fn(&T) -> U>(&self, f: F) -> RcMappedKeySignal<T, F>;
 }
 
-impl<T: Hash> AsKeySignal<T> for ReadSignal<T> {
-    fn key(&self) -> KeySignal<'_, T> {
+impl<T> AsKeySignal<T> for ReadSignal<T> {
+    fn key(&self) -> KeySignal<'_, T>
+    where
+        T: Hash,
+    {
         KeySignal(self)
     }
+
+    fn map_key<U: Hash, F: Fn(&T) -> U>(&self, f: F) -> MappedKeySignal<'_, T, F> {
+        MappedKeySignal(self, f)
+    }
 }
 
-impl<T: Hash> AsRcKeySignal<T> for RcSignal<T> {
-    fn rc_key(self) -> RcKeySignal<T> {
-        RcKeySignal(self)
+impl<T> AsRcKeySignal<T> for RcSignal<T> {
+    fn rc_key(&self) -> RcKeySignal<T>
+    where
+        T: Hash,
+    {
+        RcKeySignal(self.clone())
+    }
+
+    fn map_rc_key<U: Hash, F: Fn(&T) -> U>(&self, f: F) -> RcMappedKeySignal<T, F> {
+        RcMappedKeySignal(self.clone(), f)
     }
 }
 
@@ -396,3 +1810,41 @@ impl<T: Hash> Hash for RcKeySignal<T> {
         self.0.hash(state);
     }
 }
+
+impl<'cx, T, U: Hash, F: Fn(&T) -> U> Hash for MappedKeySignal<'cx, T, F> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.track();
+        (self.1)(&self.0.get_untracked()).hash(state);
+    }
+}
+
+impl<T, U: Hash, F: Fn(&T) -> U> Hash for RcMappedKeySignal<T, F> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.track();
+        (self.1)(&self.0.get_untracked()).hash(state);
+    }
+}
+
+impl<'cx, T: Hash + Debug> Debug for KeySignal<'cx, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.0.get_untracked().fmt(f)
+    }
+}
+
+impl<T: Hash + Debug> Debug for RcKeySignal<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.0.get_untracked().fmt(f)
+    }
+}
+
+impl<'cx, T, U: Debug, F: Fn(&T) -> U> Debug for MappedKeySignal<'cx, T, F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        (self.1)(&self.0.get_untracked()).fmt(f)
+    }
+}
+
+impl<T, U: Debug, F: Fn(&T) -> U> Debug for RcMappedKeySignal<T, F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        (self.1)(&self.0.get_untracked()).fmt(f)
+    }
+}
@@ -0,0 +1,103 @@
+use crate::QueryMeta;
+
+/// Receives query lifecycle events from the
+/// [`QueryClient`](crate::QueryClient), for debugging or telemetry. Set it
+/// with [`ClientOptions::logger`](crate::ClientOptions::logger). Keys are
+/// passed in their human readable form if the `readable-keys` feature is
+/// enabled, or as a hash otherwise.
+///
+/// Every method does nothing by default, so implementations only need to
+/// handle the events they care about.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, *, logger::Logger};
+/// # use std::{cell::RefCell, rc::Rc};
+/// # async fn fetch_user(id: u32) -> Result<String, String> { Ok(id.to_string()) }
+/// #[derive(Default)]
+/// struct Recorder(RefCell<Vec<String>>);
+///
+/// impl Logger for Recorder {
+///     fn key_changed(&self, key: &str) {
+///         self.0.borrow_mut().push(key.to_string());
+///     }
+/// }
+///
+/// let recorder = Rc::new(Recorder::default());
+/// let client = QueryClient::new(ClientOptions {
+///     logger: Some(recorder.clone()),
+///     ..Default::default()
+/// });
+///
+/// create_scope_immediate(|cx| {
+///     provide_context(cx, client);
+///     # let _ =
+///     QueryBuilder::new(("user", 3), || fetch_user(3))
+///         # .enabled(|| false)
+///         .use_query(cx);
+/// });
+/// # #[cfg(feature = "readable-keys")]
+/// assert_eq!(*recorder.0.borrow(), vec![r#"("user", 3)"#]);
+/// ```
+pub trait Logger {
+    /// A query hook was mounted, or its key changed.
+    #[allow(unused_variables)]
+    fn key_changed(&self, key: &str) {}
+
+    /// A mounted query was invalidated and is about to be refetched.
+    #[allow(unused_variables)]
+    fn invalidated(&self, key: &str) {}
+
+    /// A fetch started. This includes refetches and prefetches.
+    #[allow(unused_variables)]
+    fn fetch_started(&self, key: &str) {}
+
+    /// A fetch failed and is retried. `attempt` starts at 1.
+    #[allow(unused_variables)]
+    fn retrying(&self, key: &str, attempt: u32) {}
+
+    /// A fetch settled after all retries, successfully or not.
+    #[allow(unused_variables)]
+    fn fetch_settled(&self, key: &str, success: bool) {}
+
+    /// Like [`fetch_settled`](Logger::fetch_settled), with the query's
+    /// metadata (see [`QueryOptions::meta`](crate::QueryOptions::meta)).
+    /// Calls `fetch_settled` by default.
+    #[allow(unused_variables)]
+    fn fetch_settled_with_meta(&self, key: &str, success: bool, meta: Option<&QueryMeta>) {
+        self.fetch_settled(key, success);
+    }
+}
+
+/// A [`Logger`] that forwards events to the `log` crate at `debug` level.
+/// This is the default in debug builds. Release builds don't log by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogLogger;
+
+impl Logger for LogLogger {
+    fn key_changed(&self, key: &str) {
+        log::debug!("Key changed. New key: {key}");
+    }
+
+    fn invalidated(&self, key: &str) {
+        log::debug!("Invalidated query {key}");
+    }
+
+    fn fetch_started(&self, key: &str) {
+        log::debug!("Fetching query {key}");
+    }
+
+    fn retrying(&self, key: &str, attempt: u32) {
+        log::debug!("Retrying query {key} (attempt {attempt})");
+    }
+
+    fn fetch_settled(&self, key: &str, success: bool) {
+        if success {
+            log::debug!("Fetched query {key}");
+        } else {
+            log::debug!("Fetching query {key} failed");
+        }
+    }
+}
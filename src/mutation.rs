@@ -1,11 +1,20 @@
-use std::{future::Future, rc::Rc};
+use std::{any::Any, cell::Cell, future::Future, pin::Pin, rc::Rc, time::Duration};
 
-use sycamore::{
-    futures::spawn_local_scoped,
-    reactive::{create_ref, create_signal, use_context, ReadSignal, Scope, Signal},
+use sycamore::reactive::{
+    create_effect, create_rc_signal, create_ref, RcSignal, ReadSignal, Scope,
 };
 
-use crate::{client::QueryOptions, QueryClient, QueryData, Status};
+use crate::{
+    cache::{MutationEntry, MutationFn},
+    client::{MutationOptions, SetDataOptions},
+    AsKeys, QueryClient, QueryData, QueryKey, Status,
+};
+
+/// Undoes an optimistic update when the mutation fails.
+type Rollback = Box<dyn FnOnce()>;
+/// Applies an optimistic update and returns how to undo it, if anything was
+/// updated.
+type OptimisticUpdate<'a, Args> = Box<dyn Fn(&Rc<QueryClient>, &Args) -> Option<Rollback> + 'a>;
 
 /// The struct representing a mutation
 ///
@@ -37,34 +46,135 @@ pub struct Mutation<'a, T, E, Args> {
     pub mutate: &'a dyn Fn(Args),
 }
 
+/// A snapshot of the state of a mutation tracked by the [`QueryClient`].
+/// See [`QueryClient::mutations`].
+#[derive(Clone)]
+pub struct MutationState {
+    /// The id of the mutation. Ids are unique per client and increase in the
+    /// order mutations were started.
+    pub id: u64,
+    /// The key set in [`MutationOptions::mutation_key`], if any.
+    pub mutation_key: Option<QueryKey>,
+    /// The type erased data returned by the mutation, if any.
+    pub data: QueryData<Rc<dyn Any>, Rc<dyn Any>>,
+    /// The status of the mutation.
+    pub status: Status,
+}
+
+impl From<&MutationEntry> for MutationState {
+    fn from(entry: &MutationEntry) -> Self {
+        Self {
+            id: entry.id,
+            mutation_key: entry.key.clone(),
+            data: entry.data.get_untracked().as_ref().clone(),
+            status: *entry.status.get_untracked(),
+        }
+    }
+}
+
 impl QueryClient {
-    pub(crate) fn run_mutation<'a, T, E, Mutate, R, Args, Success>(
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn run_mutation<T, E, Mutate, R, Args, Success>(
         &self,
-        cx: Scope<'a>,
-        data: &'a Signal<QueryData<Rc<T>, Rc<E>>>,
-        status: &'a Signal<Status>,
-        mutator: &'a Mutate,
+        data: RcSignal<QueryData<Rc<T>, Rc<E>>>,
+        status: RcSignal<Status>,
+        mutator: Rc<Mutate>,
         args: Args,
-        on_success: &'a Success,
+        on_success: Rc<Success>,
+        rollbacks: Vec<Rollback>,
+        options: &MutationOptions,
     ) where
-        Mutate: Fn(Args) -> R,
-        R: Future<Output = Result<T, E>>,
-        Success: Fn(Rc<QueryClient>, Rc<T>),
-        Args: 'a,
+        Mutate: Fn(Args) -> R + 'static,
+        R: Future<Output = Result<T, E>> + 'static,
+        Success: Fn(Rc<QueryClient>, Rc<T>) + 'static,
+        Args: Clone + 'static,
+        T: 'static,
+        E: 'static,
     {
-        status.set(Status::Fetching);
-        spawn_local_scoped(cx, async move {
-            let res = mutator(args).await;
-            data.set(res.map_or_else(
-                |err| QueryData::Err(Rc::new(err)),
-                |data| QueryData::Ok(Rc::new(data)),
-            ));
-            if let QueryData::Ok(ok) = data.get().as_ref() {
-                let client = use_context::<Rc<QueryClient>>(cx);
-                on_success(client.clone(), ok.clone());
-            }
-            status.set(Status::Success);
+        let (id, cached_data, cached_status) = {
+            let mut mutation_cache = self.mutation_cache.write().unwrap();
+            let entry = mutation_cache.insert(options.mutation_key.clone());
+            (entry.id, entry.data.clone(), entry.status.clone())
+        };
+        let initial_status = (status.clone(), cached_status.clone());
+        let retries = options.retries;
+        let retry_fn = options
+            .retry_fn
+            .clone()
+            .unwrap_or_else(|| self.default_options.retry_fn.clone());
+        let client = self.rc();
+        #[cfg(feature = "tracing")]
+        let key_repr = options.mutation_key.as_ref().map(|key| self.key_repr(key));
+        let mutation_key = options.mutation_key.clone();
+        let mutation: MutationFn = Box::new(move || {
+            #[cfg(feature = "tracing")]
+            let span = crate::trace::mutation_span(key_repr.as_deref());
+            #[cfg(feature = "test-util")]
+            let record = client
+                .record_fetch_started(mutation_key.clone(), crate::testing::FetchKind::Mutation);
+            let mutation = async move {
+                #[cfg(feature = "tracing")]
+                let started = crate::timer::Instant::now();
+                cached_status.set(Status::Fetching);
+                status.set(Status::Fetching);
+                let mut res = mutator(args.clone()).await;
+                let mut attempts = 1;
+                while res.is_err() && attempts <= retries {
+                    client.delay(retry_fn(attempts - 1)).await;
+                    res = mutator(args.clone()).await;
+                    attempts += 1;
+                }
+                #[cfg(feature = "tracing")]
+                crate::trace::record_outcome(
+                    &tracing::Span::current(),
+                    started,
+                    attempts,
+                    res.is_ok(),
+                );
+                #[cfg(feature = "test-util")]
+                QueryClient::record_fetch_settled(record, attempts, res.is_ok());
+                data.set(res.map_or_else(
+                    |err| QueryData::Err(Rc::new(err)),
+                    |data| QueryData::Ok(Rc::new(data)),
+                ));
+                cached_data.set(match data.get_untracked().as_ref() {
+                    QueryData::Loading => QueryData::Loading,
+                    QueryData::Idle => QueryData::Idle,
+                    QueryData::Ok(ok) => QueryData::Ok(ok.clone() as Rc<dyn Any>),
+                    QueryData::Err(err) => QueryData::Err(err.clone() as Rc<dyn Any>),
+                });
+                match data.get_untracked().as_ref() {
+                    QueryData::Ok(ok) => on_success(client.clone(), ok.clone()),
+                    QueryData::Err(_) => rollbacks.into_iter().rev().for_each(|undo| undo()),
+                    _ => {}
+                }
+                client.mutation_cache.write().unwrap().settle(id);
+                cached_status.set(Status::Success);
+                status.set(Status::Success);
+                client.mutation_settled(
+                    mutation_key.as_ref(),
+                    cached_data.get_untracked().as_ref().clone(),
+                );
+            };
+            #[cfg(feature = "tracing")]
+            let mutation = tracing::Instrument::instrument(mutation, span);
+            Box::pin(mutation)
         });
+
+        // Queue behind already paused mutations so they're always sent in order
+        let (status, cached_status) = initial_status;
+        let has_paused = self.mutation_cache.read().unwrap().paused_count() > 0;
+        if self.is_online() && !has_paused {
+            cached_status.set(Status::Fetching);
+            status.set(Status::Fetching);
+            self.spawn(mutation());
+        } else {
+            self.mutation_cache.write().unwrap().pause(id, mutation);
+            self.update_paused_mutation_count();
+            cached_status.set(Status::Paused);
+            status.set(Status::Paused);
+            self.resume_paused_mutations();
+        }
     }
 }
 
@@ -74,9 +184,22 @@ impl QueryClient {
 ///
 /// * `cx` - The scope for the component the mutation is in.
 /// * `mutator` - The function that actually executes the mutation on the server.
-/// This can take in any type of arguments.
+///   This can take in any type of arguments.
 /// * `on_success` - Function to execute when the mutation is successful. Used to
-/// invalidate queries or update queries with data returned by the mutation.
+///   invalidate queries or update queries with data returned by the mutation.
+///   Use [`use_mutation_simple`] if you don't need it.
+///
+/// The mutator and `on_success` need to be `'static` because a mutation keeps
+/// running if the component is unmounted while it's in flight. Its state can
+/// still be observed through [`QueryClient::mutations`].
+///
+/// Mutations started while the client is offline (see
+/// [`QueryClient::set_online`]) are [`Paused`](Status::Paused) and replayed in
+/// order once the client is back online.
+///
+/// # Panics
+///
+/// Panics if no [`QueryClient`] was provided as a context.
 ///
 /// # Returns
 ///
@@ -103,35 +226,154 @@ pub fn use_mutation<'a, Args, T, E, F, R, Success>(
     on_success: Success,
 ) -> Mutation<'a, T, E, Args>
 where
-    F: Fn(Args) -> R + 'a,
-    R: Future<Output = Result<T, E>>,
-    Success: Fn(Rc<QueryClient>, Rc<T>) + 'a,
+    F: Fn(Args) -> R + 'static,
+    R: Future<Output = Result<T, E>> + 'static,
+    Success: Fn(Rc<QueryClient>, Rc<T>) + 'static,
+    Args: Clone + 'static,
+    T: 'static,
+    E: 'static,
+{
+    use_mutation_with_options(cx, mutator, on_success, MutationOptions::default())
+}
+
+/// Use a mutation without an `on_success` callback, for mutations whose
+/// result is only read from the returned `data` signal, or not at all. See
+/// [`use_mutation`] for details.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{*, mutation::{Mutation, use_mutation_simple}};
+/// # #[component]
+/// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+/// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+/// # async fn track_visit(page: String) -> Result<(), String> { Ok(()) }
+/// let Mutation { mutate, .. } = use_mutation_simple(cx, track_visit);
+///
+/// mutate("/home".to_string());
+/// # view! { cx, }
+/// # }
+/// ```
+pub fn use_mutation_simple<'a, Args, T, E, F, R>(
+    cx: Scope<'a>,
+    mutator: F,
+) -> Mutation<'a, T, E, Args>
+where
+    F: Fn(Args) -> R + 'static,
+    R: Future<Output = Result<T, E>> + 'static,
+    Args: Clone + 'static,
+    T: 'static,
+    E: 'static,
 {
-    use_mutation_with_options(cx, mutator, on_success, QueryOptions::default())
+    use_mutation(cx, mutator, |_, _| {})
 }
 
-/// Use a mutation with additional query options. For more information, see
-/// [`use_mutation`] and [`QueryOptions`]
+/// Use a mutation with additional options. For more information, see
+/// [`use_mutation`] and [`MutationOptions`]
 pub fn use_mutation_with_options<'a, Args, T, E, F, R, Success>(
     cx: Scope<'a>,
     mutator: F,
     on_success: Success,
-    _options: QueryOptions,
+    options: MutationOptions,
 ) -> Mutation<'a, T, E, Args>
 where
-    F: Fn(Args) -> R + 'a,
-    R: Future<Output = Result<T, E>>,
-    Success: Fn(Rc<QueryClient>, Rc<T>) + 'a,
+    F: Fn(Args) -> R + 'static,
+    R: Future<Output = Result<T, E>> + 'static,
+    Success: Fn(Rc<QueryClient>, Rc<T>) + 'static,
+    Args: Clone + 'static,
+    T: 'static,
+    E: 'static,
 {
-    let client = use_context::<Rc<QueryClient>>(cx).clone();
-    let data: &Signal<QueryData<Rc<T>, Rc<E>>> = create_signal(cx, QueryData::Loading);
-    let status = create_signal(cx, Status::Fetching);
-    let mutator = create_ref(cx, mutator);
-    let on_success = create_ref(cx, on_success);
+    use_mutation_inner(cx, mutator, on_success, options, Vec::new())
+}
 
-    let mutate = create_ref(cx, move |args: Args| {
-        client.run_mutation(cx, data, status, mutator, args, on_success)
-    });
+fn use_mutation_inner<'a, Args, T, E, F, R, Success>(
+    cx: Scope<'a>,
+    mutator: F,
+    on_success: Success,
+    options: MutationOptions,
+    optimistic_updates: Vec<OptimisticUpdate<'a, Args>>,
+) -> Mutation<'a, T, E, Args>
+where
+    F: Fn(Args) -> R + 'static,
+    R: Future<Output = Result<T, E>> + 'static,
+    Success: Fn(Rc<QueryClient>, Rc<T>) + 'static,
+    Args: Clone + 'static,
+    T: 'static,
+    E: 'static,
+{
+    let client = match &options.client {
+        Some(client) => client.clone(),
+        None => QueryClient::from_context(cx, "use_mutation"),
+    };
+    let data: RcSignal<QueryData<Rc<T>, Rc<E>>> = create_rc_signal(QueryData::Loading);
+    let status = create_rc_signal(Status::Fetching);
+    let mutator = Rc::new(mutator);
+    let on_success = Rc::new(on_success);
+    // Set once `mutate` is called, so a restored mutation that's still running
+    // can't overwrite the state of a newer one
+    let superseded = Rc::new(Cell::new(false));
+
+    if let (true, Some(key)) = (options.keep_state, &options.mutation_key) {
+        let latest = client
+            .mutation_cache
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.key.as_ref() == Some(key))
+            .last()
+            .map(|entry| (entry.data.clone(), entry.status.clone()));
+        if let Some((cached_data, cached_status)) = latest {
+            let data = data.clone();
+            let status = status.clone();
+            let superseded = superseded.clone();
+            let client = client.clone();
+            let key = key.clone();
+            create_effect(cx, move || {
+                let cached_data = cached_data.get().as_ref().clone();
+                let cached_status = *cached_status.get();
+                if superseded.get() {
+                    return;
+                }
+                data.set(
+                    cached_data
+                        .map(|data| {
+                            data.downcast()
+                                .unwrap_or_else(|_| client.type_mismatch::<T>(&key))
+                        })
+                        .map_err(|err| {
+                            err.downcast()
+                                .unwrap_or_else(|_| client.type_mismatch::<E>(&key))
+                        }),
+                );
+                status.set(cached_status);
+            });
+        }
+    }
+
+    let mutate = {
+        let data = data.clone();
+        let status = status.clone();
+        create_ref(cx, move |args: Args| {
+            superseded.set(true);
+            let rollbacks = optimistic_updates
+                .iter()
+                .filter_map(|update| update(&client, &args))
+                .collect();
+            client.run_mutation(
+                data.clone(),
+                status.clone(),
+                mutator.clone(),
+                args,
+                on_success.clone(),
+                rollbacks,
+                &options,
+            )
+        })
+    };
+    let data = create_ref(cx, data);
+    let status = create_ref(cx, status);
 
     Mutation {
         data,
@@ -139,3 +381,262 @@ where
         status,
     }
 }
+
+/// A builder for mutations with more advanced options, like optimistic
+/// updates. See [`use_mutation`] for the common case.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{*, mutation::{Mutation, MutationBuilder}};
+/// # #[component]
+/// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+/// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+/// # async fn rename_user(name: String) -> Result<String, String> { Ok(name) }
+/// let Mutation { data, status, mutate } = MutationBuilder::new(
+///     rename_user,
+///     |client, _| client.invalidate_queries("users"),
+/// )
+/// .mutation_key("rename")
+/// .use_mutation(cx);
+/// # view! { cx, }
+/// # }
+/// ```
+pub struct MutationBuilder<'a, Args, F, Success> {
+    mutator: F,
+    on_success: Success,
+    options: MutationOptions,
+    optimistic_updates: Vec<OptimisticUpdate<'a, Args>>,
+}
+
+/// A mutator whose result is transformed by
+/// [`MutationBuilder::with_select`].
+type SelectedMutator<Args, U, E> = Box<dyn Fn(Args) -> Pin<Box<dyn Future<Output = Result<U, E>>>>>;
+
+impl<'a, Args: Clone + 'static> MutationBuilder<'a, Args, (), ()> {
+    /// Creates a new mutation builder that transforms the result of
+    /// `mutator` with `select` before it's stored. `on_success` and the
+    /// mutation's `data` receive the selected value, which is useful to unwrap
+    /// a response envelope once instead of in every callback. Errors are left
+    /// as they are. To keep the raw result available, return it from `select`
+    /// alongside the part you need.
+    ///
+    /// `on_success` comes after `select` because its argument type depends on
+    /// it. See [`use_mutation`] for the other parameters.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{*, mutation::{Mutation, MutationBuilder}};
+    /// # #[component]
+    /// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+    /// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+    /// struct Envelope<T> {
+    ///     data: T,
+    ///     request_id: String,
+    /// }
+    ///
+    /// async fn rename_user(name: String) -> Result<Envelope<String>, String> {
+    ///     Ok(Envelope { data: name, request_id: "1".to_string() })
+    /// }
+    ///
+    /// let Mutation { data, status, mutate } = MutationBuilder::with_select(
+    ///     rename_user,
+    ///     |envelope| envelope.data,
+    ///     |client, name| client.set_query_data("name", name),
+    /// )
+    /// .use_mutation(cx);
+    /// # view! { cx, }
+    /// # }
+    /// ```
+    pub fn with_select<T, U, E, F, R, Success>(
+        mutator: F,
+        select: impl Fn(T) -> U + 'static,
+        on_success: Success,
+    ) -> MutationBuilder<'a, Args, SelectedMutator<Args, U, E>, Success>
+    where
+        F: Fn(Args) -> R + 'static,
+        R: Future<Output = Result<T, E>> + 'static,
+        Success: Fn(Rc<QueryClient>, Rc<U>) + 'static,
+        T: 'static,
+        U: 'static,
+        E: 'static,
+    {
+        let select = Rc::new(select);
+        let mutator: SelectedMutator<Args, U, E> = Box::new(move |args| {
+            let res = mutator(args);
+            let select = select.clone();
+            Box::pin(async move { res.await.map(|data| select(data)) })
+        });
+        MutationBuilder::new(mutator, on_success)
+    }
+}
+
+impl<'a, Args, T, E, F, R, Success> MutationBuilder<'a, Args, F, Success>
+where
+    F: Fn(Args) -> R + 'static,
+    R: Future<Output = Result<T, E>> + 'static,
+    Success: Fn(Rc<QueryClient>, Rc<T>) + 'static,
+    Args: Clone + 'static,
+    T: 'static,
+    E: 'static,
+{
+    /// Creates a new mutation builder. See [`use_mutation`] for the
+    /// parameters.
+    pub fn new(mutator: F, on_success: Success) -> Self {
+        Self {
+            mutator,
+            on_success,
+            options: MutationOptions::default(),
+            optimistic_updates: Vec::new(),
+        }
+    }
+
+    /// Overrides all [`MutationOptions`] at once.
+    pub fn options(mut self, options: MutationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// A key for the mutation, see [`MutationOptions::mutation_key`].
+    pub fn mutation_key(mut self, key: impl AsKeys) -> Self {
+        self.options.mutation_key = Some(key.as_keys());
+        self
+    }
+
+    /// Use `client` instead of the [`QueryClient`] provided as a context.
+    pub fn client(mut self, client: Rc<QueryClient>) -> Self {
+        self.options.client = Some(client);
+        self
+    }
+
+    /// Restore the state of the last mutation with the same key when the hook
+    /// is created, see [`MutationOptions::keep_state`].
+    pub fn keep_state(mut self) -> Self {
+        self.options.keep_state = true;
+        self
+    }
+
+    /// Retry the mutation up to `retries` times if it fails, see
+    /// [`MutationOptions::retries`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{*, mutation::MutationBuilder, testing::TestQueryClient};
+    /// # use std::{cell::Cell, rc::Rc};
+    /// let test = TestQueryClient::new();
+    /// let calls = Rc::new(Cell::new(0));
+    /// // Fails twice, then succeeds
+    /// let flaky = {
+    ///     let calls = calls.clone();
+    ///     move |name: String| {
+    ///         calls.set(calls.get() + 1);
+    ///         let ok = calls.get() > 2;
+    ///         async move { if ok { Ok(name) } else { Err("timeout") } }
+    ///     }
+    /// };
+    ///
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, test.client());
+    ///     let rename = MutationBuilder::new(flaky, |_, _| {})
+    ///         .retries(2)
+    ///         .use_mutation(cx);
+    ///     (rename.mutate)("Alice".to_string());
+    ///     test.flush();
+    ///     assert_eq!(calls.get(), 3);
+    ///     assert!(matches!(*rename.data.get(), QueryData::Ok(_)));
+    /// });
+    /// # }
+    /// ```
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.options.retries = retries;
+        self
+    }
+
+    /// The function for the timeout between retries, see
+    /// [`MutationOptions::retry_fn`].
+    pub fn retry_fn(mut self, retry_fn: impl Fn(u32) -> Duration + 'static) -> Self {
+        self.options.retry_fn = Some(Rc::new(retry_fn));
+        self
+    }
+
+    /// Optimistically patch the cached data for `key` as soon as `mutate` is
+    /// called. `update` receives the current data and the mutation arguments
+    /// and returns the new data. If the mutation fails, the data from before
+    /// the patch is restored. Nothing is patched if there's no data cached
+    /// for `key`. Can be called multiple times to patch several queries.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cached data for `key` isn't a `Q`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{*, mutation::MutationBuilder};
+    /// # async fn add_todo(todo: String) -> Result<(), String> { Ok(()) }
+    /// create_scope_immediate(|cx| {
+    ///     let client = QueryClient::new(ClientOptions::default());
+    ///     provide_context(cx, client.clone());
+    ///     client.set_query_data("todos", vec!["Write docs".to_string()]);
+    ///     // Queue the mutation instead of sending it, the patch still applies
+    ///     client.set_online(false);
+    ///
+    ///     let add = MutationBuilder::new(add_todo, |_, _| {})
+    ///         .optimistic_update("todos", |todos: &Vec<String>, todo: &String| {
+    ///             let mut todos = todos.clone();
+    ///             todos.push(todo.clone());
+    ///             todos
+    ///         })
+    ///         .use_mutation(cx);
+    ///     (add.mutate)("Write tests".to_string());
+    ///
+    ///     assert_eq!(
+    ///         client.query_data::<_, Vec<String>>("todos").unwrap().as_slice(),
+    ///         ["Write docs", "Write tests"]
+    ///     );
+    /// });
+    /// ```
+    pub fn optimistic_update<K, Q>(mut self, key: K, update: impl Fn(&Q, &Args) -> Q + 'a) -> Self
+    where
+        K: AsKeys + 'a,
+        Q: 'static,
+    {
+        self.optimistic_updates
+            .push(Box::new(move |client, args| -> Option<Rollback> {
+                let id = key.as_keys();
+                client.register_key_repr(&id, &key);
+                let previous = client.cache.read().unwrap().get(&id)?;
+                let data = previous
+                    .clone()
+                    .downcast::<Q>()
+                    .unwrap_or_else(|_| client.type_mismatch::<Q>(&id));
+                let options = SetDataOptions {
+                    preserve_timestamp: true,
+                };
+                client.set_erased_query_data(id.clone(), Rc::new(update(&data, args)), options);
+                let client = client.clone();
+                Some(Box::new(move || {
+                    client.set_erased_query_data(id, previous, options)
+                }))
+            }));
+        self
+    }
+
+    /// Use the mutation. See [`use_mutation`] for details.
+    pub fn use_mutation(self, cx: Scope<'a>) -> Mutation<'a, T, E, Args> {
+        use_mutation_inner(
+            cx,
+            self.mutator,
+            self.on_success,
+            self.options,
+            self.optimistic_updates,
+        )
+    }
+}
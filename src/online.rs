@@ -0,0 +1,117 @@
+use std::{cell::Cell, rc::Rc};
+
+use wasm_bindgen::{closure::Closure, JsCast};
+
+use crate::{Listeners, Subscription};
+
+/// Tells the [`QueryClient`](crate::QueryClient) whether the network is
+/// available and when that changes. The client starts out with
+/// [`is_online`](OnlineManager::is_online) and follows the manager's updates
+/// from then on, pausing mutations while offline and replaying them when the
+/// connection comes back. Set it with
+/// [`ClientOptions::online_manager`](crate::ClientOptions::online_manager).
+///
+/// The default is [`NavigatorOnline`]. Provide your own if you have a better
+/// source of truth, like a heartbeat endpoint. For tests, use
+/// [`ManualOnline`].
+pub trait OnlineManager {
+    /// Whether the network is currently available.
+    fn is_online(&self) -> bool;
+
+    /// Call `callback` with the new connectivity state every time it changes,
+    /// until the returned [`Subscription`] is dropped.
+    fn subscribe(&self, callback: Rc<dyn Fn(bool)>) -> Subscription;
+}
+
+/// The default [`OnlineManager`], using `navigator.onLine` and the `online`
+/// and `offline` events. Outside of the browser, the network is always
+/// available.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NavigatorOnline;
+
+impl OnlineManager for NavigatorOnline {
+    fn is_online(&self) -> bool {
+        if !cfg!(target_arch = "wasm32") {
+            return true;
+        }
+        web_sys::window().is_none_or(|window| window.navigator().on_line())
+    }
+
+    fn subscribe(&self, callback: Rc<dyn Fn(bool)>) -> Subscription {
+        if !cfg!(target_arch = "wasm32") {
+            return Subscription::none();
+        }
+        let Some(window) = web_sys::window() else {
+            return Subscription::none();
+        };
+        let listener = Closure::<dyn Fn()>::new(move || callback(NavigatorOnline.is_online()));
+        for event in ["online", "offline"] {
+            let _ =
+                window.add_event_listener_with_callback(event, listener.as_ref().unchecked_ref());
+        }
+        Subscription::new(move || {
+            for event in ["online", "offline"] {
+                let _ = window
+                    .remove_event_listener_with_callback(event, listener.as_ref().unchecked_ref());
+            }
+        })
+    }
+}
+
+/// An [`OnlineManager`] that's controlled with
+/// [`set_online`](ManualOnline::set_online). Useful for tests, or to report
+/// connectivity from something like a heartbeat. Starts out online.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::{*, online::ManualOnline};
+/// # use std::rc::Rc;
+/// let online = ManualOnline::default();
+/// let client = QueryClient::new(ClientOptions {
+///     online_manager: Rc::new(online.clone()),
+///     # ssr: false,
+///     ..Default::default()
+/// });
+///
+/// online.set_online(false);
+/// assert!(!client.is_online());
+/// online.set_online(true);
+/// assert!(client.is_online());
+/// ```
+#[derive(Clone)]
+pub struct ManualOnline(Rc<ManualOnlineState>);
+
+struct ManualOnlineState {
+    online: Cell<bool>,
+    listeners: Rc<Listeners>,
+}
+
+impl Default for ManualOnline {
+    fn default() -> Self {
+        Self(Rc::new(ManualOnlineState {
+            online: Cell::new(true),
+            listeners: Rc::default(),
+        }))
+    }
+}
+
+impl ManualOnline {
+    /// Set whether the network is available, notifying subscribers if it
+    /// changed.
+    pub fn set_online(&self, online: bool) {
+        if self.0.online.replace(online) != online {
+            self.0.listeners.notify(online);
+        }
+    }
+}
+
+impl OnlineManager for ManualOnline {
+    fn is_online(&self) -> bool {
+        self.0.online.get()
+    }
+
+    fn subscribe(&self, callback: Rc<dyn Fn(bool)>) -> Subscription {
+        self.0.listeners.subscribe(callback)
+    }
+}
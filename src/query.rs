@@ -1,16 +1,31 @@
+#[cfg(feature = "test-util")]
+use crate::testing::FetchKind;
 use crate::{
-    as_rc, client::QueryOptions, AsKeys, DataSignal, Fetcher, QueryClient, QueryData, Status,
+    cache::InFlight, client::QueryOptions, timer, AsKeys, DataSource, ErasedData, Fetcher,
+    IntoKeys, QueryClient, QueryData, QueryEvent, QueryKey, StateSignal, Status,
 };
-use fluvio_wasm_timer::Delay;
+#[cfg(feature = "tracing")]
+use crate::{timer::Instant, trace};
+use futures_core::Stream;
 use std::any::Any;
-use std::{future::Future, rc::Rc};
-use sycamore::{
-    futures::spawn_local,
-    reactive::{
-        create_effect, create_memo, create_rc_signal, create_ref, create_selector, use_context,
-        ReadSignal, Scope, Signal,
-    },
+use std::{
+    cell::{Cell, RefCell},
+    future::{poll_fn, Future},
+    marker::PhantomData,
+    pin::Pin,
+    rc::Rc,
+    time::Duration,
 };
+use sycamore::reactive::{
+    create_effect, create_memo, create_rc_signal, create_ref, create_selector,
+    create_selector_with, on_cleanup, untrack, ReadSignal, Scope,
+};
+
+/// Creates the fetcher for a key, see [`QueryClient::set_default_query_fn`].
+pub(crate) type DefaultQueryFn = Rc<dyn Fn(&QueryClient, &QueryKey) -> Fetcher>;
+
+/// The data and status of a query, see [`Query::state`].
+pub type QueryState<T, E> = (QueryData<Rc<T>, Rc<E>>, Status);
 
 /// The struct representing a query
 ///
@@ -22,7 +37,7 @@ use sycamore::{
 /// # #[component]
 /// # pub fn App<G: Html>(cx: Scope) -> View<G> {
 /// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
-/// let Query { data, status, refetch } = use_query(
+/// let Query { data, status, refetch, .. } = use_query(
 ///     cx,
 ///     ("hello", "World"),
 ///     || async { Result::<_, ()>::Ok("World".to_string()) }
@@ -31,112 +46,700 @@ use sycamore::{
 /// # view! { cx, }
 /// # }
 /// ```
+///
+/// `data`, `status` and `data_source` are separate signals, and only notify
+/// their observers when their value actually changes. When a fetch settles,
+/// data and status change together, so an effect reading both `data` and
+/// `status` runs once for each. `state` holds both and changes in a single
+/// write, so an effect reading it runs once per settled fetch:
+///
+/// ```
+/// # #[cfg(feature = "test-util")] {
+/// # use std::cell::RefCell;
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, testing::TestQueryClient};
+/// # async fn fetch_user() -> Result<String, String> { unreachable!() }
+/// let test = TestQueryClient::new();
+/// create_scope_immediate(|cx| {
+///     provide_context(cx, test.client());
+///     let Query { state, refetch, .. } = use_query(cx, "user", fetch_user);
+///     let runs = create_ref(cx, RefCell::new(Vec::new()));
+///     create_effect(cx, move || {
+///         let (data, status) = state.get().as_ref().clone();
+///         runs.borrow_mut().push((data.is_ok(), status));
+///     });
+///     test.resolve("user", "Alice".to_string());
+///     refetch();
+///     test.resolve("user", "Bob".to_string());
+///     assert_eq!(
+///         *runs.borrow(),
+///         [
+///             (false, Status::Fetching),
+///             (true, Status::Success),
+///             (true, Status::Fetching),
+///             (true, Status::Success),
+///         ]
+///     );
+/// });
+/// # }
+/// ```
 pub struct Query<'a, T, E, F: Fn()> {
     /// The data returned by the query. See [`QueryData`].
     pub data: &'a ReadSignal<QueryData<Rc<T>, Rc<E>>>,
     /// The status of the query. See [`Status`].
-    pub status: Rc<Signal<Status>>,
-    /// A function to trigger a refetch of the query and all queries with the
-    /// same key.
+    pub status: &'a ReadSignal<Status>,
+    /// The data and status of the query together. `data` and `status` are
+    /// views of this signal.
+    pub state: &'a ReadSignal<QueryState<T, E>>,
+    /// A function to trigger a refetch of the query. Only the query's exact
+    /// key is refetched, use [`QueryClient::refetch_queries`] to refetch all
+    /// queries that share a prefix.
     pub refetch: &'a F,
+    /// Like `refetch`, but keeps serving the cached data and fetches even if
+    /// it's still fresh.
+    pub force_refetch: &'a dyn Fn(),
+    /// Like `refetch`, but returns a future that resolves once the fetch has
+    /// settled. If the query is already fetching, the future waits for that
+    /// fetch instead of starting another one.
+    pub refetch_async: &'a dyn Fn() -> Pin<Box<dyn Future<Output = ()>>>,
+    /// Where the current data came from. See [`DataSource`].
+    pub data_source: &'a ReadSignal<DataSource>,
 }
 
 impl QueryClient {
     pub(crate) fn find_query(
         &self,
-        key: &[u64],
+        key: &QueryKey,
         new_hook: bool,
-    ) -> Option<(Rc<DataSignal>, Rc<Signal<Status>>, Fetcher)> {
-        let data = self.data_signals.read().unwrap().get(key);
-        let status = self.status_signals.read().unwrap().get(key);
+    ) -> Option<(Rc<StateSignal>, Fetcher)> {
+        let state = self.state_signals.read().unwrap().get(key)?;
         let fetcher = self.fetchers.read().unwrap().get(key)?.clone();
-        let (data, status) = match (data, status) {
-            (None, None) => None,
-            (None, Some(status)) => {
-                let data = if let Some(data) = self.cache.read().unwrap().get(key) {
-                    QueryData::Ok(data)
-                } else {
-                    QueryData::Loading
-                };
-                let data = as_rc(create_rc_signal(data));
-                if new_hook {
-                    self.data_signals
-                        .write()
-                        .unwrap()
-                        .insert(key.to_vec(), data.clone());
-                }
-                Some((data, status))
+        if new_hook {
+            self.mounted_keys.write().unwrap().insert(key.clone());
+        }
+        Some((state, fetcher))
+    }
+
+    /// The typed version of the erased data of `key`. This is where every
+    /// query hook turns the client's type erased data back into its own
+    /// types, panicking with a [`TypeMismatch`](crate::TypeMismatch) if they
+    /// don't match. Downcasting only compares type ids, so it's cheap enough
+    /// to do in each hook.
+    pub(crate) fn downcast_data<T: 'static, E: 'static>(
+        &self,
+        key: &QueryKey,
+        data: &ErasedData,
+    ) -> QueryData<Rc<T>, Rc<E>> {
+        data.clone()
+            .map(|data| {
+                data.downcast()
+                    .unwrap_or_else(|_| self.type_mismatch::<T>(key))
+            })
+            .map_err(|err| {
+                err.downcast()
+                    .unwrap_or_else(|_| self.type_mismatch::<E>(key))
+            })
+    }
+
+    /// Register the options of a query mounted with `key`, see
+    /// [`observed_options`](Self::observed_options).
+    pub(crate) fn add_observer(&self, key: &QueryKey, options: Rc<QueryOptions>) {
+        self.observer_options
+            .write()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .push(options);
+    }
+
+    pub(crate) fn remove_observer(&self, key: &QueryKey, options: &Rc<QueryOptions>) {
+        let mut observers = self.observer_options.write().unwrap();
+        if let Some(list) = observers.get_mut(key) {
+            if let Some(i) = list.iter().position(|other| Rc::ptr_eq(other, options)) {
+                list.remove(i);
             }
-            (Some(data), None) => {
-                let status = as_rc(create_rc_signal(Status::Success));
-                if new_hook {
-                    self.status_signals
-                        .write()
-                        .unwrap()
-                        .insert(key.to_vec(), status.clone());
-                }
-                Some((data, status))
+            if list.is_empty() {
+                observers.remove(key);
             }
-            (Some(data), Some(status)) => Some((data, status)),
-        }?;
-        Some((data, status, fetcher))
+        }
+    }
+
+    /// The options to fetch `key` with. If queries are mounted with the key,
+    /// their options are combined as described in [`QueryOptions`], and
+    /// `options` only contributes the options that apply to each query on its
+    /// own. Otherwise `options` is used as is.
+    pub(crate) fn observed_options(&self, key: &QueryKey, options: &QueryOptions) -> QueryOptions {
+        let observers = self.observer_options.read().unwrap();
+        let Some(observers) = observers.get(key) else {
+            return options.clone();
+        };
+        let merged: Vec<_> = observers
+            .iter()
+            .map(|observer| self.default_options.merge(observer))
+            .collect();
+        let on_retry: Vec<_> = observers
+            .iter()
+            .filter_map(|observer| observer.on_retry.clone())
+            .collect();
+        QueryOptions {
+            cache_expiration: merged.iter().map(|o| o.cache_expiration).max(),
+            stale_time: merged.iter().map(|o| o.stale_time).min(),
+            retries: merged.iter().map(|o| o.retries).max(),
+            background_retries: merged
+                .iter()
+                .map(|o| o.background_retries.unwrap_or(o.retries))
+                .max(),
+            retry_fn: observers.iter().find_map(|o| o.retry_fn.clone()),
+            retry_delay_with_error: observers
+                .iter()
+                .find_map(|o| o.retry_delay_with_error.clone()),
+            on_retry: (!on_retry.is_empty()).then(|| {
+                Rc::new(move |retry| {
+                    for on_retry in &on_retry {
+                        on_retry(retry);
+                    }
+                }) as Rc<dyn Fn(u32)>
+            }),
+            debounce: options.debounce,
+            refetch_interval: options.refetch_interval,
+            meta: options.meta.clone(),
+        }
     }
 
-    pub(crate) fn insert_query(
+    pub(crate) fn insert_query<K: AsKeys + ?Sized>(
         &self,
-        key: Vec<u64>,
-        data: Rc<DataSignal>,
-        status: Rc<Signal<Status>>,
+        key: &K,
+        id: QueryKey,
+        state: Rc<StateSignal>,
         fetcher: Fetcher,
     ) {
-        self.data_signals.write().unwrap().insert(key.clone(), data);
-        self.status_signals
+        self.register_key_repr(&id, key);
+        self.state_signals
             .write()
             .unwrap()
-            .insert(key.clone(), status);
-        self.fetchers.write().unwrap().insert(key, fetcher);
+            .insert(id.clone(), state);
+        self.mounted_keys.write().unwrap().insert(id.clone());
+        self.fetchers.write().unwrap().insert(id.clone(), fetcher);
+        self.emit(|| QueryEvent::Added { key: id });
+        self.cache_changed();
     }
 
     pub(crate) fn run_query(
-        self: Rc<Self>,
-        key: &[u64],
-        data: Rc<DataSignal>,
-        status: Rc<Signal<Status>>,
+        &self,
+        key: &QueryKey,
+        state: Rc<StateSignal>,
         fetcher: Fetcher,
         options: &QueryOptions,
     ) {
-        let options = self.default_options.merge(options);
-        if let Some(cached) = {
+        let options = &self.observed_options(key, options);
+        let stale_time = self.default_options.merge(options).stale_time;
+        let cached = {
             let cache = self.cache.read().unwrap();
-            cache.get(key)
-        } {
-            data.set(QueryData::Ok(cached));
-            self.clone().invalidate_queries(vec![key.to_vec()]);
-        } else if *status.get_untracked() != Status::Fetching {
-            status.set(Status::Fetching);
-            let key = key.to_vec();
-            spawn_local(async move {
-                let mut res = fetcher().await;
+            cache
+                .get(key)
+                .map(|cached| (cached, cache.is_stale(key, stale_time)))
+        };
+        #[cfg(feature = "tracing")]
+        trace::cache_lookup(
+            &self.key_repr(key),
+            match &cached {
+                Some((_, true)) => "stale",
+                Some(_) => "hit",
+                None => "miss",
+            },
+        );
+        if let Some((cached, stale)) = cached {
+            self.set_data_source(key, DataSource::Cache);
+            // There's nothing to refresh stale data with while rendering on
+            // the server
+            let refresh = stale && !self.default_options.ssr;
+            let status = match state.get_untracked().1 {
+                Status::Fetching => Status::Fetching,
+                status if refresh => status,
+                _ => Status::Success,
+            };
+            state.set((QueryData::Ok(cached), status));
+            if refresh {
+                self.invalidate_queries(vec![key.clone()]);
+            }
+        } else {
+            self.fetch_query(key, state, fetcher, options);
+        }
+    }
+
+    /// Run the fetcher and write the result to the cache, regardless of what's
+    /// cached. Does nothing if the query is already fetching, or when
+    /// rendering on the server.
+    fn fetch_query(
+        &self,
+        key: &QueryKey,
+        state: Rc<StateSignal>,
+        fetcher: Fetcher,
+        options: &QueryOptions,
+    ) {
+        if self.default_options.ssr {
+            return;
+        }
+        let (data, status) = state.get_untracked().as_ref().clone();
+        if status != Status::Fetching {
+            // Nobody is waiting on a refetch of data that's already shown
+            let background = matches!(data, QueryData::Ok(_));
+            // The options of the mounted queries are combined again before
+            // every retry and when the fetch settles, so queries mounted
+            // while it's running count too
+            let requested = options.clone();
+            let in_flight = InFlight::default();
+            self.in_flight
+                .write()
+                .unwrap()
+                .insert(key.clone(), in_flight.clone());
+            state.set((data, Status::Fetching));
+            let key = key.clone();
+            self.log(|logger| logger.fetch_started(&self.key_repr(&key)));
+            self.emit(|| QueryEvent::Fetching { key: key.clone() });
+            #[cfg(feature = "tracing")]
+            let span = trace::fetch_span(&self.key_repr(&key));
+            #[cfg(feature = "test-util")]
+            let record = self.record_fetch_started(Some(key.clone()), FetchKind::Query);
+            let client = self.rc();
+            let acquire = self.fetch_limit.acquire();
+            let fetch = async move {
+                let permit = acquire.await;
+                #[cfg(feature = "tracing")]
+                let started = Instant::now();
+                let mut res = client.call_fetcher(&key, &fetcher).await;
                 let mut retries = 0;
-                while res.is_err() && retries < options.retries {
-                    Delay::new((options.retry_fn)(retries)).await.unwrap();
-                    res = fetcher().await;
+                while let Err(err) = &res {
+                    let observed = client.observed_options(&key, &requested);
+                    let options = client.default_options.merge(&observed);
+                    let max_retries = if background {
+                        options.background_retries.unwrap_or(options.retries)
+                    } else {
+                        options.retries
+                    };
+                    if retries >= max_retries {
+                        break;
+                    }
+                    client.log(|logger| logger.retrying(&client.key_repr(&key), retries + 1));
+                    if let Some(on_retry) = &observed.on_retry {
+                        on_retry(retries + 1);
+                    }
+                    let delay = observed
+                        .retry_delay_with_error
+                        .and_then(|retry_delay| retry_delay(retries, err.as_ref()))
+                        .unwrap_or_else(|| (options.retry_fn)(retries));
+                    client.delay(delay).await;
+                    res = client.call_fetcher(&key, &fetcher).await;
                     retries += 1;
                 }
-                data.set(res.map_or_else(QueryData::Err, QueryData::Ok));
-                if let QueryData::Ok(data) = data.get_untracked().as_ref() {
-                    self.cache
-                        .write()
-                        .unwrap()
-                        .insert(key, data.clone(), &options);
-                }
-                status.set(Status::Success);
-            });
+                drop(permit);
+                client.emit(|| QueryEvent::Settled {
+                    key: key.clone(),
+                    success: res.is_ok(),
+                });
+                #[cfg(feature = "tracing")]
+                trace::record_outcome(&tracing::Span::current(), started, retries + 1, res.is_ok());
+                #[cfg(feature = "test-util")]
+                QueryClient::record_fetch_settled(record, retries + 1, res.is_ok());
+                // A stream that ended without any item leaves the data as it was
+                let data = if is_empty_stream(&res) {
+                    state.get_untracked().0.clone()
+                } else {
+                    client.set_data_source(&key, DataSource::Fetch);
+                    if let Ok(data) = &res {
+                        let options = client
+                            .default_options
+                            .merge(&client.observed_options(&key, &requested));
+                        client
+                            .cache
+                            .write()
+                            .unwrap()
+                            .insert(key.clone(), data.clone(), &options);
+                        client.emit(|| QueryEvent::Updated { key: key.clone() });
+                    }
+                    res.clone().into()
+                };
+                client.in_flight.write().unwrap().remove(&key);
+                // Data and status in one write, so observers run once
+                state.set((data, Status::Success));
+                in_flight.settle();
+                client.cache_changed();
+                client.query_settled(&key, &res);
+            };
+            #[cfg(feature = "tracing")]
+            let fetch = tracing::Instrument::instrument(fetch, span);
+            self.spawn(fetch);
+        }
+    }
+
+    /// The fetches currently in flight for queries whose keys start with any
+    /// of `prefixes`.
+    pub(crate) fn in_flight_matching(&self, prefixes: &[QueryKey]) -> Vec<InFlight> {
+        self.in_flight
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| prefixes.iter().any(|prefix| key.starts_with(prefix)))
+            .map(|(_, in_flight)| in_flight.clone())
+            .collect()
+    }
+
+    /// Fetch a query ahead of time, so its data is already cached when a
+    /// component using it mounts. Does nothing if fresh data (see
+    /// [`ClientOptions::stale_time`](crate::ClientOptions::stale_time)) is
+    /// already cached. If a query with the same key is currently in use, this
+    /// refetches it. Errors aren't cached, so a failed prefetch is simply
+    /// fetched again when the query is used. Does nothing when rendering on
+    /// the server (see [`ClientOptions::ssr`](crate::ClientOptions::ssr)),
+    /// fetch the data yourself and pass it to
+    /// [`hydrate_query_data`](QueryClient::hydrate_query_data) instead.
+    ///
+    /// A query mounted while the prefetch is running waits for it instead of
+    /// fetching again, and the prefetch counts as in flight for
+    /// [`invalidate_queries_async`](QueryClient::invalidate_queries_async).
+    /// Retries use the client's options, see [`ClientOptions`](crate::ClientOptions).
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, testing::TestQueryClient};
+    /// # async fn fetch_user() -> Result<String, String> { unreachable!() }
+    /// let test = TestQueryClient::new();
+    /// // For example on hovering a link to the profile
+    /// test.client().prefetch_query("user", fetch_user);
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, test.client());
+    ///     let Query { data, .. } = use_query(cx, "user", fetch_user);
+    ///     assert_eq!(test.pending_fetches().len(), 1);
+    ///     test.resolve("user", "Alice".to_string());
+    ///     assert!(data.get().is_ok());
+    /// });
+    /// # }
+    /// ```
+    ///
+    /// # Paginated lists
+    ///
+    /// There's no dedicated infinite query. Paginated lists use one key per
+    /// page, so prefetching the first pages means prefetching each of their
+    /// keys. Components rendering those pages then start out with cached data.
+    /// For data that was fetched elsewhere, like during server side rendering,
+    /// use [`hydrate_query_data`](QueryClient::hydrate_query_data) per page
+    /// instead.
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # use std::rc::Rc;
+    /// # async fn fetch_page(page: u32) -> Result<Vec<String>, String> { Ok(vec![]) }
+    /// fn prefetch_first_pages(client: Rc<QueryClient>, pages: u32) {
+    ///     for page in 0..pages {
+    ///         client.prefetch_query(("posts", page), move || fetch_page(page));
+    ///     }
+    /// }
+    /// ```
+    pub fn prefetch_query<K, T, E, F, R>(&self, key: K, fetcher: F)
+    where
+        K: AsKeys,
+        F: Fn() -> R + 'static,
+        R: Future<Output = Result<T, E>> + 'static,
+        T: 'static,
+        E: 'static,
+    {
+        let id = key.as_keys();
+        self.register_type::<T>(&id);
+        self.register_key_repr(&id, &key);
+        let fetcher = erase_fetcher(&id, Rc::new(move |_| fetcher()));
+        self.prefetch_erased(id, fetcher);
+    }
+
+    /// Like [`prefetch_query`](QueryClient::prefetch_query), but fetches with
+    /// the default query function for `key`, see
+    /// [`set_default_query_fn`](QueryClient::set_default_query_fn). Does
+    /// nothing if there is none.
+    pub fn prefetch_default_query(&self, key: impl AsKeys) {
+        let id = key.as_keys();
+        let Some(fetcher) = self.default_fetcher(&id) else {
+            return;
+        };
+        self.register_key_repr(&id, &key);
+        self.prefetch_erased(id, fetcher);
+    }
+
+    /// Get the cached data for `key` right away, and fetch it in the
+    /// background if it's missing or stale, like
+    /// [`prefetch_query`](QueryClient::prefetch_query) does. This is the
+    /// imperative counterpart of [`use_query`] for code that has no `Scope`,
+    /// like services or mutation callbacks. Returns `None` if nothing is
+    /// cached yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cached data isn't a `T`, see
+    /// [`try_query_data`](QueryClient::try_query_data).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore_query::{*, testing::TestQueryClient};
+    /// # use std::time::Duration;
+    /// # async fn fetch_settings() -> Result<String, String> { unreachable!() }
+    /// let test = TestQueryClient::with_options(ClientOptions {
+    ///     stale_time: Duration::from_secs(60),
+    ///     ..ClientOptions::test_defaults()
+    /// });
+    /// let client = test.client();
+    ///
+    /// assert_eq!(client.ensure_query_data("settings", fetch_settings), None);
+    /// test.resolve("settings", "dark mode".to_string());
+    ///
+    /// // Fresh data is returned without fetching again
+    /// let settings = client.ensure_query_data("settings", fetch_settings);
+    /// assert_eq!(settings.as_deref(), Some(&"dark mode".to_string()));
+    /// assert!(test.pending_fetches().is_empty());
+    /// # }
+    /// ```
+    pub fn ensure_query_data<K, T, E, F, R>(&self, key: K, fetcher: F) -> Option<Rc<T>>
+    where
+        K: AsKeys,
+        F: Fn() -> R + 'static,
+        R: Future<Output = Result<T, E>> + 'static,
+        T: 'static,
+        E: 'static,
+    {
+        let data = self
+            .try_query_data::<_, T>(key.as_keys())
+            .unwrap_or_else(|err| panic!("{err}"));
+        self.prefetch_query(key, fetcher);
+        data
+    }
+
+    /// Prefetch `id` with an already erased fetcher, see
+    /// [`prefetch_query`](QueryClient::prefetch_query).
+    pub(crate) fn prefetch_erased(&self, id: QueryKey, fetcher: Fetcher) {
+        {
+            let cache = self.cache.read().unwrap();
+            if cache.get(&id).is_some() && !cache.is_stale(&id, self.default_options.stale_time) {
+                return;
+            }
+        }
+        if let Some((state, fetcher)) = self.find_query(&id, false) {
+            self.run_query(&id, state, fetcher, &QueryOptions::default());
+            return;
+        }
+        if self.default_options.ssr {
+            return;
+        }
+        // Registered like a query's signals for as long as the fetch holds
+        // them, so a query mounted while it's running shares the fetch
+        // instead of starting its own
+        let cached = self.cache.read().unwrap().get(&id);
+        let state: Rc<StateSignal> = Rc::new(create_rc_signal((
+            cached.map_or(QueryData::Loading, QueryData::Ok),
+            Status::Idle,
+        )));
+        self.state_signals
+            .write()
+            .unwrap()
+            .insert(id.clone(), state.clone());
+        self.fetchers
+            .write()
+            .unwrap()
+            .insert(id.clone(), fetcher.clone());
+        self.fetch_query(&id, state, fetcher, &QueryOptions::default());
+    }
+
+    /// Set the query function used for all keys starting with `prefix`, so
+    /// queries under it don't need their own fetcher. Use them with
+    /// [`use_default_query`] and
+    /// [`prefetch_default_query`](QueryClient::prefetch_default_query).
+    /// [`invalidate_queries`](QueryClient::invalidate_queries) also refetches
+    /// cached data under `prefix` that no component is using anymore, since
+    /// the client knows how to fetch it.
+    ///
+    /// `query_fn` receives a [`QueryContext`] with the key it's run for. If
+    /// several prefixes match a key, the longest one wins. Setting a function
+    /// for the same prefix again replaces it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{*, query::{use_default_query, QueryContext}};
+    /// # async fn http_get(key: QueryKey) -> Result<String, String> { Ok(String::new()) }
+    /// let client = QueryClient::new(ClientOptions {
+    ///     # ssr: true,
+    ///     ..Default::default()
+    /// });
+    /// client.set_default_query_fn("api", |ctx: QueryContext| http_get(ctx.key));
+    ///
+    /// # create_scope_immediate(|cx| {
+    /// #   provide_context(cx, client.clone());
+    /// let user = use_default_query::<_, String, String>(cx, ("api", "/users", 1));
+    /// # });
+    /// client.prefetch_default_query(("api", "/users", 2));
+    /// ```
+    ///
+    /// Invalidation refetches data even if no component uses it:
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore_query::{*, query::QueryContext, testing::TestQueryClient};
+    /// # async fn http_get(key: QueryKey) -> Result<String, String> { unreachable!() }
+    /// let test = TestQueryClient::new();
+    /// let client = test.client();
+    /// client.set_default_query_fn("api", |ctx: QueryContext| http_get(ctx.key));
+    /// client.set_query_data(("api", "/users", 1), "Alice".to_string());
+    ///
+    /// client.invalidate_queries("api");
+    /// assert_eq!(test.pending_fetches(), vec![("api", "/users", 1).as_keys()]);
+    /// test.resolve(("api", "/users", 1), "Alicia".to_string());
+    /// assert_eq!(
+    ///     client.query_data::<_, String>(("api", "/users", 1)).as_deref(),
+    ///     Some(&"Alicia".to_string())
+    /// );
+    /// # }
+    /// ```
+    pub fn set_default_query_fn<K, T, E, F, R>(&self, prefix: K, query_fn: F)
+    where
+        K: AsKeys,
+        F: Fn(QueryContext) -> R + 'static,
+        R: Future<Output = Result<T, E>> + 'static,
+        T: 'static,
+        E: 'static,
+    {
+        let prefix = prefix.as_keys();
+        let query_fn = Rc::new(query_fn);
+        let make_fetcher: DefaultQueryFn = Rc::new(move |client, key| {
+            client.register_type::<T>(key);
+            erase_fetcher(key, query_fn.clone())
+        });
+        let mut default_fns = self.default_query_fns.write().unwrap();
+        default_fns.retain(|(existing, _)| *existing != prefix);
+        default_fns.push((prefix, make_fetcher));
+    }
+
+    /// The fetcher created by the default query function with the longest
+    /// prefix of `key`, if there is one.
+    pub(crate) fn default_fetcher(&self, key: &QueryKey) -> Option<Fetcher> {
+        let make_fetcher = self
+            .default_query_fns
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(prefix, _)| key.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, make_fetcher)| make_fetcher.clone())?;
+        Some(make_fetcher(self, key))
+    }
+
+    /// Refetch the query with exactly `key`, if it's mounted. Without `force`,
+    /// the cache entry is removed like it would be by invalidation.
+    pub(crate) fn refetch_query(
+        &self,
+        key: &QueryKey,
+        options: &QueryOptions,
+        force: bool,
+    ) -> InFlight {
+        if !force {
+            self.cache.write().unwrap().remove(key);
         }
+        if let Some((state, fetcher)) = self.find_query(key, false) {
+            self.fetch_query(key, state, fetcher, options);
+        }
+        let in_flight = self.in_flight.read().unwrap().get(key).cloned();
+        in_flight.unwrap_or_else(InFlight::settled)
+    }
+
+    /// Refetch all queries whose keys start with any of the keys passed in.
+    /// Without `force`, this is the same as
+    /// [`invalidate_queries`](QueryClient::invalidate_queries). With `force`,
+    /// the cache entries are kept and the fetchers of all mounted queries run
+    /// regardless of how fresh the cached data is, replacing the cache
+    /// entries when they succeed. Use this for "pull to refresh".
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// # let client = QueryClient::new(ClientOptions::default());
+    /// client.set_query_data("posts", vec!["Hello".to_string()]);
+    /// client.refetch_queries("posts", true);
+    /// // The cached data is still served until the refetch finishes
+    /// assert!(client.query_data::<_, Vec<String>>("posts").is_some());
+    /// ```
+    pub fn refetch_queries(&self, queries: impl IntoKeys, force: bool) {
+        if !force {
+            self.invalidate_queries(queries);
+            return;
+        }
+        let queries = queries.into_keys();
+        for query in self.mounted_matching(&queries) {
+            if let Some((state, fetcher)) = self.find_query(&query, false) {
+                self.fetch_query(&query, state, fetcher, &QueryOptions::default());
+            }
+        }
+    }
+
+    /// An owned handle to refetch the query with exactly `key`. Unlike the
+    /// `refetch` returned by [`use_query`], the handle is `'static` and can be
+    /// cloned, so it can be stored in signals, moved into timers or passed
+    /// through props.
+    ///
+    /// Refetching invalidates the cached data for `key` and fetches it again
+    /// if a query with that key is mounted. If none is, nothing is fetched
+    /// and the next query to mount with the key fetches fresh data.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore_query::*;
+    /// let client = QueryClient::new(ClientOptions::default());
+    /// client.set_query_data("todos", vec!["Write docs".to_string()]);
+    ///
+    /// let refetch = client.refetch_handle("todos");
+    /// let on_timer = {
+    ///     let refetch = refetch.clone();
+    ///     move || refetch.refetch()
+    /// };
+    /// on_timer();
+    /// assert!(client.query_data::<_, Vec<String>>("todos").is_none());
+    /// ```
+    pub fn refetch_handle(&self, key: impl AsKeys) -> RefetchHandle {
+        let id = key.as_keys();
+        self.register_key_repr(&id, &key);
+        RefetchHandle {
+            client: self.rc(),
+            key: id,
+        }
+    }
+}
+
+/// An owned, clonable handle to refetch a query. See
+/// [`QueryClient::refetch_handle`].
+#[derive(Clone)]
+pub struct RefetchHandle {
+    client: Rc<QueryClient>,
+    key: QueryKey,
+}
+
+impl RefetchHandle {
+    /// Refetch the query. See [`QueryClient::refetch_handle`] for what
+    /// happens if it isn't mounted.
+    pub fn refetch(&self) {
+        self.client
+            .refetch_query(&self.key, &QueryOptions::default(), false);
     }
 
-    pub(crate) fn refetch_query(self: Rc<Self>, key: &[u64]) {
-        self.invalidate_queries(vec![key.to_vec()]);
+    /// Refetch the query and return a future that resolves once the fetch
+    /// has settled. Resolves right away if nothing was fetched.
+    pub fn refetch_async(&self) -> impl Future<Output = ()> {
+        self.client
+            .refetch_query(&self.key, &QueryOptions::default(), false)
+    }
+
+    /// The key of the query this handle refetches.
+    pub fn key(&self) -> &QueryKey {
+        &self.key
     }
 }
 
@@ -146,24 +749,66 @@ impl QueryClient {
 ///
 /// * `cx` - The Scope of the containing component
 /// * `key` - A unique key for this query. Any queries sharing this key will
-/// have the same data and status signals. If your query takes arguments, it's
-/// expected to add them to the key tuple. Keys in your key tuple only need to
-/// implement `Hash`. Using a key tuple is preferrable to using a formatted
-/// string because the tuple allows for invalidating groups of queries that share
-/// the same top level key. Why is this a closure instead of a value? Because I need to track the
-/// signals used in it. There is a more ergonomic implementation but it requires specialization or
-/// a change in sycamore's `Hash` implementation.
+///   have the same data and status signals. If your query takes arguments, it's
+///   expected to add them to the key tuple. Keys in your key tuple only need to
+///   implement `Hash`. Using a key tuple is preferrable to using a formatted
+///   string because the tuple allows for invalidating groups of queries that share
+///   the same top level key. For keys that depend on signals, see [`use_query_fn`].
 /// * `fetcher` - The asynchronous function used to fetch the data. This needs
-/// to be static because it's stored and automatically rerun if the data in the
-/// cache is stale or the query is invalidated.
+///   to be static because it's stored and automatically rerun if the data in the
+///   cache is stale or the query is invalidated.
 ///
 /// # Signals in Keys
 ///
 /// Currently, Sycamore uses the `untracked_get` function in its [`Hash`](std::hash::Hash)
-/// implementation for signals. This means changes won't be tracked by default. If you want the
-/// query to refetch every time the signal in the key changes, use `signal.key()`/`signal.rc_key()`
-/// from the [`AsKeySignal`](crate::AsKeySignal) and [`AsRcKeySignal`](crate::AsRcKeySignal) traits
-/// respectively.
+/// implementation for signals. This means changes won't be tracked by default. The easiest way
+/// to get a key that updates with your signals is [`use_query_fn`], which takes the key as a
+/// closure. Alternatively, use `signal.key()`/`signal.rc_key()` from the
+/// [`AsKeySignal`](crate::AsKeySignal) and [`AsRcKeySignal`](crate::AsRcKeySignal) traits
+/// respectively to track individual signals in a key value.
+///
+/// The key is hashed once when the hook is created, and after that only when a signal tracked
+/// while hashing it changes. Changes to any other signal in the component don't cause the key to
+/// be hashed again, so expensive [`AsKeys`] implementations are fine.
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{*, query::QueryBuilder};
+/// # use std::{cell::Cell, rc::Rc};
+/// struct Filters<'a> {
+///     category: &'a ReadSignal<String>,
+///     hashed: Rc<Cell<u32>>,
+/// }
+///
+/// impl AsKeys for Filters<'_> {
+///     fn as_keys(&self) -> QueryKey {
+///         self.hashed.set(self.hashed.get() + 1);
+///         let mut key = QueryKey::new();
+///         key.push("posts");
+///         key.push(&*self.category.get());
+///         key
+///     }
+/// }
+///
+/// create_scope_immediate(|cx| {
+/// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+///     let category = create_signal(cx, "rust".to_string());
+///     let unrelated = create_signal(cx, 0);
+///     let hashed = Rc::new(Cell::new(0));
+///     let filters = Filters { category, hashed: hashed.clone() };
+///     # let _ =
+///     QueryBuilder::new(filters, || async { Result::<_, ()>::Ok(()) })
+///         # .enabled(|| false)
+///         .use_query(cx);
+///     assert_eq!(hashed.get(), 1);
+///
+///     unrelated.set(1);
+///     assert_eq!(hashed.get(), 1);
+///
+///     category.set("sycamore".to_string());
+///     assert_eq!(hashed.get(), 2);
+/// });
+/// ```
 ///
 /// # Example
 ///
@@ -173,7 +818,7 @@ impl QueryClient {
 /// # #[component]
 /// # pub fn App<G: Html>(cx: Scope) -> View<G> {
 /// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
-/// let Query { data, status, refetch } = use_query(
+/// let Query { data, status, refetch, .. } = use_query(
 ///     cx,
 ///     ("hello", "World"),
 ///     || async { Result::<_, ()>::Ok("World".to_string()) }
@@ -183,13 +828,96 @@ impl QueryClient {
 /// # }
 /// ```
 ///
+/// # Refetching
+///
+/// `refetch` only refetches the query's exact key, so refetching a list
+/// doesn't refetch the details of every item in it. Use
+/// [`QueryClient::refetch_queries`] to refetch all queries sharing a prefix.
+///
+/// ```
+/// # #[cfg(feature = "test-util")] {
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, testing::TestQueryClient, *};
+/// # async fn fetch_todos() -> Result<Vec<String>, String> { unreachable!() }
+/// # async fn fetch_todo(id: u32) -> Result<String, String> { unreachable!() }
+/// let test = TestQueryClient::new();
+/// let client = test.client();
+/// create_scope_immediate(|cx| {
+///     provide_context(cx, client.clone());
+///     let todos = use_query(cx, "todos", fetch_todos);
+///     let todo = use_query(cx, ("todos", 1), || fetch_todo(1));
+///     test.resolve("todos", vec!["Write docs".to_string()]);
+///     test.resolve(("todos", 1), "Write docs".to_string());
+///
+///     // Only fetches `"todos"`, `("todos", 1)` keeps its data
+///     (todos.refetch)();
+///     assert_eq!(test.pending_fetches(), vec!["todos".as_keys()]);
+///     test.resolve("todos", vec!["Write more docs".to_string()]);
+///     assert_eq!(*todo.status.get(), Status::Success);
+///
+///     // Fetches both
+///     client.refetch_queries("todos", false);
+///     let pending = test.pending_fetches();
+///     assert_eq!(pending.len(), 2);
+///     assert!(pending.contains(&("todos", 1).as_keys()));
+/// });
+/// # }
+/// ```
+///
+/// To do something once the new data has arrived, use `refetch_async`:
+///
+/// ```
+/// # use sycamore::{prelude::*, futures::spawn_local_scoped};
+/// # use sycamore_query::{*, query::{Query, use_query}};
+/// # async fn fetch_todos() -> Result<Vec<String>, String> { Ok(vec![]) }
+/// # fn show_toast(_: &str) {}
+/// # #[component]
+/// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+/// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+/// let Query { refetch_async, .. } = use_query(cx, "todos", fetch_todos);
+/// let refresh = move |_| {
+///     spawn_local_scoped(cx, async move {
+///         refetch_async().await;
+///         show_toast("Refreshed!");
+///     })
+/// };
+/// view! { cx, button(on:click=refresh) { "Refresh" } }
+/// # }
+/// ```
+///
 /// # Notes
 ///
 /// This will crash your application if two queries with the same key but different
 /// types are used. Data is stored as `Rc<dyn Any>` internally and downcast for
 /// each `use_query` invocation. If the type doesn't match, it will panic. This
 /// shouldn't be a problem because different queries should never have exactly
-/// the same key, but it's worth noting.
+/// the same key, but it's worth noting. In debug builds, the panic happens
+/// when the second query is created and names both types.
+///
+/// ```should_panic
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, ClientOptions, QueryClient};
+/// create_scope_immediate(|cx| {
+///     let client = QueryClient::new(ClientOptions::default());
+///     client.set_query_data("user", "Ferris".to_string());
+///     provide_context(cx, client);
+///     // Panics: `user` holds a `String`
+///     let id = use_query(cx, "user", || async { Ok::<_, ()>(3u64) });
+/// });
+/// ```
+///
+/// # Panics
+///
+/// Panics if no [`QueryClient`] was provided as a context.
+///
+/// ```should_panic
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::prelude::*;
+/// create_scope_immediate(|cx| {
+///     // Missing `provide_context(cx, QueryClient::new(ClientOptions::default()))`
+///     use_query(cx, "hello", || async { Ok::<_, ()>(()) });
+/// });
+/// ```
 ///
 pub fn use_query<'a, K, T, E, F, R>(
     cx: Scope<'a>,
@@ -221,59 +949,1066 @@ where
     T: 'static,
     E: 'static,
 {
+    let fetcher = Rc::new(move |_| fetcher());
+    use_query_inner(
+        cx,
+        key,
+        move |_, key| erase_fetcher(key, fetcher.clone()),
+        options,
+        None,
+        |data| data,
+        None,
+    )
+}
+
+/// Information about the query a fetcher is run for. See [`use_query_ctx`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct QueryContext {
+    /// The hashed key the fetcher is run for.
+    pub key: QueryKey,
+}
+
+/// Use a query whose fetcher receives a [`QueryContext`] with the key it's
+/// run for. This avoids capturing the same signals in both the key and the
+/// fetcher, where they could drift apart. For everything else see
+/// [`use_query`].
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{*, query::{use_query_ctx, QueryContext}};
+/// # #[component]
+/// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+/// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+/// # async fn fetch_page(key: QueryKey) -> Result<Vec<String>, String> { Ok(vec![]) }
+/// let page = create_signal(cx, 1);
+/// let posts = use_query_ctx(cx, ("posts", page.key()), |ctx: QueryContext| {
+///     fetch_page(ctx.key)
+/// });
+/// # view! { cx, }
+/// # }
+/// ```
+pub fn use_query_ctx<'a, K, T, E, F, R>(
+    cx: Scope<'a>,
+    key: K,
+    fetcher: F,
+) -> Query<'a, T, E, impl Fn() + 'a>
+where
+    K: AsKeys + 'a,
+    F: Fn(QueryContext) -> R + 'static,
+    R: Future<Output = Result<T, E>> + 'static,
+    T: 'static,
+    E: 'static,
+{
+    let fetcher = Rc::new(fetcher);
+    use_query_inner(
+        cx,
+        key,
+        move |_, key| erase_fetcher(key, fetcher.clone()),
+        QueryOptions::default(),
+        None,
+        |data| data,
+        None,
+    )
+}
+
+/// Use a query with a key that's computed by a closure. Any signal read in
+/// `key_fn` is tracked, so the query switches to the new key whenever the
+/// computed key changes, without needing
+/// [`AsKeySignal`](crate::AsKeySignal). Signal changes that don't change the
+/// key don't cause a refetch. For everything else see [`use_query`].
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{*, query::use_query_fn};
+/// # use std::time::Duration;
+/// create_scope_immediate(|cx| {
+/// #   let client = QueryClient::new(ClientOptions {
+/// #       stale_time: Duration::from_secs(60),
+/// #       ..ClientOptions::default()
+/// #   });
+/// #   provide_context(cx, client.clone());
+/// #   client.set_query_data(("user", 1), "Alice".to_string());
+/// #   client.set_query_data(("user", 2), "Bob".to_string());
+/// #   async fn fetch_user() -> Result<String, String> { Ok(String::new()) }
+///     let id = create_signal(cx, 1);
+///     let user = use_query_fn(cx, || ("user", *id.get()), fetch_user);
+///     assert_eq!(user.data.get_data().ok().as_deref(), Some(&"Alice".to_string()));
+///
+///     id.set(2);
+///     assert_eq!(user.data.get_data().ok().as_deref(), Some(&"Bob".to_string()));
+///
+///     // Late updates for the previous key, like a slow response, don't leak
+///     // into the query anymore
+///     client.set_query_data(("user", 1), "Carol".to_string());
+///     assert_eq!(user.data.get_data().ok().as_deref(), Some(&"Bob".to_string()));
+/// });
+/// ```
+pub fn use_query_fn<'a, K, KF, T, E, F, R>(
+    cx: Scope<'a>,
+    key_fn: KF,
+    fetcher: F,
+) -> Query<'a, T, E, impl Fn() + 'a>
+where
+    K: AsKeys,
+    KF: Fn() -> K + 'a,
+    F: Fn() -> R + 'static,
+    R: Future<Output = Result<T, E>> + 'static,
+    T: 'static,
+    E: 'static,
+{
+    let fetcher = Rc::new(move |_| fetcher());
+    use_query_inner(
+        cx,
+        KeyFn(key_fn),
+        move |_, key| erase_fetcher(key, fetcher.clone()),
+        QueryOptions::default(),
+        None,
+        |data| data,
+        None,
+    )
+}
+
+/// Use a query whose fetcher returns a [`Stream`] of results instead of a
+/// single one, for endpoints that send partial results like server-sent
+/// events or chunked responses. The `data` signal is updated with every item
+/// while the status stays [`Status::Fetching`], and the last item is cached
+/// and marks the query as [`Status::Success`] once the stream ends. Each item
+/// replaces the data, so use something like `StreamExt::scan` to accumulate
+/// chunks. For everything else see [`use_query`].
+///
+/// An `Err` item ends the fetch with that error, and retries start a new
+/// stream from the beginning. A stream that ends without any item, like a
+/// connection closed before the first event, settles the query as
+/// [`Status::Success`] without changing its data. Invalidating or refetching the query calls the
+/// fetcher for a new stream, and the data keeps showing the last item of the
+/// previous one until the new stream's first item arrives. Like any other
+/// fetch, a stream that's still running isn't interrupted or restarted, so
+/// invalidating the query while it streams does nothing and streams that
+/// never end can't be refetched.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, query::use_streaming_query};
+/// # use futures::stream::{self, Stream, StreamExt};
+/// # fn fetch_chunks() -> impl Stream<Item = Result<String, String>> {
+/// #     stream::iter(vec![Ok("Hello".to_string()), Ok(", world".to_string())])
+/// # }
+/// #[component]
+/// fn Log<G: Html>(cx: Scope) -> View<G> {
+///     let Query { data, .. } = use_streaming_query(cx, "log", || {
+///         // Show everything received so far
+///         fetch_chunks().scan(String::new(), |text, chunk| {
+///             let res = chunk.map(|chunk| {
+///                 text.push_str(&chunk);
+///                 text.clone()
+///             });
+///             async move { Some(res) }
+///         })
+///     });
+///     view! { cx, (data.get_data().ok().map(|text| text.to_string()).unwrap_or_default()) }
+/// }
+/// ```
+///
+/// With the `tokio` feature, streams run on tokio's `LocalSet` outside the
+/// browser:
+///
+/// ```
+/// # #[cfg(feature = "tokio")] {
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, query::use_streaming_query, *};
+/// # use futures::stream;
+/// # use std::{cell::RefCell, rc::Rc};
+/// # let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+/// # tokio::task::LocalSet::new().block_on(&runtime, async {
+/// let client = QueryClient::new(ClientOptions::default());
+/// let seen = Rc::new(RefCell::new(Vec::new()));
+/// let statuses = Rc::new(RefCell::new(Vec::new()));
+/// let _scope = create_scope({
+///     let client = client.clone();
+///     let (seen, statuses) = (seen.clone(), statuses.clone());
+///     move |cx| {
+///         provide_context(cx, client);
+///         let Query { data, .. } = use_streaming_query(cx, "progress", || {
+///             stream::iter(vec![Ok::<_, String>(10u32), Ok(60), Ok(100)])
+///         });
+///         create_effect(cx, move || seen.borrow_mut().push(data.get_data().ok().map(|n| *n)));
+///         // Closed before sending anything
+///         let Query { data, status, .. } = use_streaming_query(cx, "events", || {
+///             stream::empty::<Result<String, String>>()
+///         });
+///         create_effect(cx, move || statuses.borrow_mut().push((*status.get(), data.get().is_loading())));
+///     }
+/// });
+/// while client.is_fetching("progress") || client.is_fetching("events") {
+///     tokio::task::yield_now().await;
+/// }
+/// // Every item was shown as it arrived
+/// assert_eq!(*seen.borrow(), vec![None, Some(10), Some(60), Some(100)]);
+/// // The empty stream settled without data
+/// assert_eq!(statuses.borrow().last(), Some(&(Status::Success, true)));
+/// # });
+/// # }
+/// ```
+///
+/// Under a `TestQueryClient`, the stream
+/// isn't created and the data resolved by the test counts as the last item.
+///
+/// ```
+/// # #[cfg(feature = "test-util")] {
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, query::use_streaming_query, testing::TestQueryClient};
+/// # use futures::stream::{self, Stream};
+/// # fn fetch_chunks() -> impl Stream<Item = Result<String, String>> { stream::empty() }
+/// let test = TestQueryClient::new();
+/// create_scope_immediate(|cx| {
+///     provide_context(cx, test.client());
+///     let Query { data, status, .. } = use_streaming_query(cx, "log", fetch_chunks);
+///     test.resolve("log", "Hello, world".to_string());
+///     assert_eq!(data.get_data().ok().as_deref(), Some(&"Hello, world".to_string()));
+///     assert_eq!(*status.get(), Status::Success);
+/// });
+/// # }
+/// ```
+pub fn use_streaming_query<'a, K, T, E, F, S>(
+    cx: Scope<'a>,
+    key: K,
+    fetcher: F,
+) -> Query<'a, T, E, impl Fn() + 'a>
+where
+    K: AsKeys + 'a,
+    F: Fn() -> S + 'static,
+    S: Stream<Item = Result<T, E>> + 'static,
+    T: 'static,
+    E: 'static,
+{
+    let fetcher = Rc::new(fetcher);
+    use_query_inner(
+        cx,
+        key,
+        move |client, key| erase_stream_fetcher(client, key, fetcher.clone()),
+        QueryOptions::default(),
+        None,
+        |data| data,
+        None,
+    )
+}
+
+/// Use a query fetched by the default query function for its key, see
+/// [`QueryClient::set_default_query_fn`]. The data and error types can't be
+/// inferred from a fetcher, so they usually need to be named. For everything
+/// else see [`use_query`].
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{*, query::{use_default_query, QueryContext}};
+/// # async fn http_get(key: QueryKey) -> Result<Vec<String>, String> { Ok(vec![]) }
+/// #[component]
+/// fn Todos<G: Html>(cx: Scope) -> View<G> {
+///     let todos = use_default_query::<_, Vec<String>, String>(cx, ("api", "/todos"));
+///     view! { cx, }
+/// }
+///
+/// #[component]
+/// fn App<G: Html>(cx: Scope) -> View<G> {
+///     let client = QueryClient::new(ClientOptions::default());
+///     client.set_default_query_fn("api", |ctx: QueryContext| http_get(ctx.key));
+///     provide_context(cx, client);
+///     view! { cx, Todos {} }
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics if no default query function matches the key.
+pub fn use_default_query<'a, K, T, E>(cx: Scope<'a>, key: K) -> Query<'a, T, E, impl Fn() + 'a>
+where
+    K: AsKeys + 'a,
+    T: 'static,
+    E: 'static,
+{
+    use_query_inner(
+        cx,
+        key,
+        |client, key| {
+            client.default_fetcher(key).unwrap_or_else(|| {
+                panic!(
+                    "`use_default_query` was called with key {}, but no default query function \
+                    matches it. Set one with `QueryClient::set_default_query_fn`.",
+                    client.key_repr(key)
+                )
+            })
+        },
+        QueryOptions::default(),
+        None,
+        |data| data,
+        None,
+    )
+}
+
+/// Erase a typed fetcher into a [`Fetcher`] for `key`.
+fn erase_fetcher<T, E, F, R>(key: &QueryKey, fetcher: Rc<F>) -> Fetcher
+where
+    F: Fn(QueryContext) -> R + 'static,
+    R: Future<Output = Result<T, E>> + 'static,
+    T: 'static,
+    E: 'static,
+{
+    let context = QueryContext { key: key.clone() };
+    Rc::new(move || {
+        let fut = fetcher(context.clone());
+        Box::pin(async move {
+            fut.await
+                .map(|data| -> Rc<dyn Any> { Rc::new(data) })
+                .map_err(|err| -> Rc<dyn Any> { Rc::new(err) })
+        })
+    })
+}
+
+/// The result of a streaming fetch whose stream ended without any item. The
+/// query settles, but keeps the data it had.
+struct EmptyStream;
+
+/// Whether `res` is the result of a stream that ended without any item.
+pub(crate) fn is_empty_stream(res: &Result<Rc<dyn Any>, Rc<dyn Any>>) -> bool {
+    res.as_ref().is_ok_and(|data| data.is::<EmptyStream>())
+}
+
+/// Like [`erase_fetcher`], but shows every item of the stream in the mounted
+/// queries as it arrives. The last item is the result of the fetch.
+fn erase_stream_fetcher<T, E, F, S>(client: &QueryClient, key: &QueryKey, fetcher: Rc<F>) -> Fetcher
+where
+    F: Fn() -> S + 'static,
+    S: Stream<Item = Result<T, E>> + 'static,
+    T: 'static,
+    E: 'static,
+{
+    // Weak, because the client owns its fetchers
+    let client = Rc::downgrade(&client.rc());
+    let key = key.clone();
+    Rc::new(move || {
+        let mut stream = Box::pin(fetcher());
+        let client = client.clone();
+        let key = key.clone();
+        Box::pin(async move {
+            let mut last = None;
+            while let Some(item) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+                let data: Rc<dyn Any> =
+                    Rc::new(item.map_err(|err| -> Rc<dyn Any> { Rc::new(err) })?);
+                if let Some(client) = client.upgrade() {
+                    client.write_data(
+                        key.clone(),
+                        DataSource::Fetch,
+                        QueryData::Ok(data.clone()),
+                        None,
+                    );
+                }
+                last = Some(data);
+            }
+            Ok(last.unwrap_or_else(|| Rc::new(EmptyStream)))
+        })
+    })
+}
+
+/// Whether `a` and `b` hold the same data. Compares pointers, so it works
+/// for data that isn't `PartialEq`.
+fn same_data<T: ?Sized, E: ?Sized>(
+    a: &QueryData<Rc<T>, Rc<E>>,
+    b: &QueryData<Rc<T>, Rc<E>>,
+) -> bool {
+    match (a, b) {
+        (QueryData::Loading, QueryData::Loading) | (QueryData::Idle, QueryData::Idle) => true,
+        (QueryData::Ok(a), QueryData::Ok(b)) => Rc::ptr_eq(a, b),
+        (QueryData::Err(a), QueryData::Err(b)) => Rc::ptr_eq(a, b),
+        _ => false,
+    }
+}
+
+/// A key computed by a closure, see [`use_query_fn`].
+struct KeyFn<F>(F);
+
+impl<K: AsKeys, F: Fn() -> K> AsKeys for KeyFn<F> {
+    fn as_keys(&self) -> QueryKey {
+        (self.0)().as_keys()
+    }
+
+    fn key_repr(&self) -> Option<String> {
+        (self.0)().key_repr()
+    }
+}
+
+/// A builder for queries with more advanced, typed options. This avoids
+/// having to pass a dozen arguments to a hook for the uncommon cases, while
+/// [`use_query`] stays short for the common one.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{*, query::{Query, QueryBuilder}};
+/// # #[component]
+/// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+/// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+/// let user_id = create_signal(cx, None::<u32>);
+/// let Query { data, status, refetch, .. } = QueryBuilder::new(
+///     ("user", 1),
+///     || async { Result::<_, ()>::Ok(vec!["Hello".to_string(), "World".to_string()]) }
+/// )
+/// .retries(0)
+/// .enabled(|| user_id.get().is_some())
+/// .select(|messages| messages.len())
+/// .use_query(cx);
+///
+/// # view! { cx, }
+/// # }
+/// ```
+pub struct QueryBuilder<'a, K, T, E, F, U = T> {
+    key: K,
+    fetcher: F,
+    options: QueryOptions,
+    enabled: Option<Box<dyn Fn() -> bool + 'a>>,
+    select: Box<dyn Fn(Rc<T>) -> Rc<U> + 'a>,
+    client: Option<Rc<QueryClient>>,
+    _error: PhantomData<E>,
+}
+
+impl<'a, K, T, E, F, R> QueryBuilder<'a, K, T, E, F>
+where
+    K: AsKeys + 'a,
+    F: Fn() -> R + 'static,
+    R: Future<Output = Result<T, E>> + 'static,
+    T: 'static,
+    E: 'static,
+{
+    /// Creates a new query builder. See [`use_query`] for the parameters.
+    pub fn new(key: K, fetcher: F) -> Self {
+        Self {
+            key,
+            fetcher,
+            options: QueryOptions::default(),
+            enabled: None,
+            select: Box::new(|data| data),
+            client: None,
+            _error: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, T, E, F, R, U> QueryBuilder<'a, K, T, E, F, U>
+where
+    K: AsKeys + 'a,
+    F: Fn() -> R + 'static,
+    R: Future<Output = Result<T, E>> + 'static,
+    T: 'static,
+    E: 'static,
+    U: 'a,
+{
+    /// Overrides all [`QueryOptions`] at once.
+    pub fn options(mut self, options: QueryOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// How long the cached data is kept after the last query using it
+    /// unmounts, like `gcTime` in TanStack Query. Overrides the client's
+    /// [`cache_expiration`](crate::ClientOptions::cache_expiration) for this
+    /// query,
+    /// even for data that was prefetched or hydrated with another lifetime.
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, testing::{ManualClock, TestQueryClient}, *};
+    /// # use std::{rc::Rc, time::Duration};
+    /// # async fn fetch_report() -> Result<String, String> { unreachable!() }
+    /// let clock = ManualClock::default();
+    /// let test = TestQueryClient::with_options(ClientOptions {
+    ///     clock: Rc::new(clock.clone()),
+    ///     ..ClientOptions::test_defaults()
+    /// });
+    /// let client = test.client();
+    /// client.set_query_data("report", "Q3".to_string());
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, client.clone());
+    ///     // Expensive to compute, so keep it around for an hour
+    ///     let _ = QueryBuilder::new("report", fetch_report)
+    ///         .stale_time(Duration::from_secs(60 * 60))
+    ///         .cache_expiration(Duration::from_secs(60 * 60))
+    ///         .use_query(cx);
+    ///     assert!(test.pending_fetches().is_empty());
+    /// });
+    ///
+    /// // Long past the client's 5 minutes, counted from the unmount
+    /// clock.advance(Duration::from_secs(30 * 60));
+    /// assert!(client.query_data::<_, String>("report").is_some());
+    /// clock.advance(Duration::from_secs(31 * 60));
+    /// assert!(client.query_data::<_, String>("report").is_none());
+    /// # }
+    /// ```
+    #[doc(alias = "gc_time")]
+    pub fn cache_expiration(mut self, cache_expiration: Duration) -> Self {
+        self.options.cache_expiration = Some(cache_expiration);
+        self
+    }
+
+    /// The time after which cached data is considered stale. A query mounted
+    /// while fresh data is in the cache uses that data without refetching.
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, testing::TestQueryClient};
+    /// # use std::time::Duration;
+    /// # async fn fetch_user() -> Result<String, String> { unreachable!() }
+    /// let test = TestQueryClient::new();
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, test.client());
+    ///     for _ in 0..2 {
+    ///         let _ = QueryBuilder::new("user", fetch_user)
+    ///             .stale_time(Duration::from_secs(60))
+    ///             .use_query(cx);
+    ///     }
+    ///     // Both queries wait for the same fetch
+    ///     assert_eq!(test.pending_fetches().len(), 1);
+    ///     test.resolve("user", "Ferris".to_string());
+    /// });
+    ///
+    /// // Mounting again while the data is fresh doesn't fetch
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, test.client());
+    ///     let user = QueryBuilder::new("user", fetch_user)
+    ///         .stale_time(Duration::from_secs(60))
+    ///         .use_query(cx);
+    ///     assert_eq!(user.data.get_data().ok().as_deref(), Some(&"Ferris".to_string()));
+    ///     assert!(test.pending_fetches().is_empty());
+    /// });
+    /// # }
+    /// ```
+    pub fn stale_time(mut self, stale_time: Duration) -> Self {
+        self.options.stale_time = Some(stale_time);
+        self
+    }
+
+    /// The number of times to retry the query if it fails.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.options.retries = Some(retries);
+        self
+    }
+
+    /// The number of times to retry a refetch while the query already shows
+    /// data, see [`QueryOptions::background_retries`]. For example, retry
+    /// the first load while the user is waiting, but let background refetches
+    /// fail fast instead of hammering the server.
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, testing::TestQueryClient};
+    /// # async fn fetch_user() -> Result<String, String> { unreachable!() }
+    /// let test = TestQueryClient::new();
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, test.client());
+    ///     let Query { data, force_refetch, .. } = QueryBuilder::new("user", fetch_user)
+    ///         .retries(3)
+    ///         .background_retries(0)
+    ///         .use_query(cx);
+    ///
+    ///     // The first load is retried
+    ///     test.reject("user", "Timeout".to_string());
+    ///     assert!(!test.pending_fetches().is_empty());
+    ///     test.resolve("user", "Alice".to_string());
+    ///
+    ///     // A background refetch isn't
+    ///     force_refetch();
+    ///     test.reject("user", "Timeout".to_string());
+    ///     assert!(test.pending_fetches().is_empty());
+    ///     assert!(data.get().is_err());
+    /// });
+    /// # }
+    /// ```
+    pub fn background_retries(mut self, retries: u32) -> Self {
+        self.options.background_retries = Some(retries);
+        self
+    }
+
+    /// The function for the timeout between retries.
+    pub fn retry_fn(mut self, retry_fn: impl Fn(u32) -> Duration + 'static) -> Self {
+        self.options.retry_fn = Some(Rc::new(retry_fn));
+        self
+    }
+
+    /// Like [`retry_fn`](QueryBuilder::retry_fn), but the delay can depend on
+    /// the error of the failed attempt, for example to respect a `Retry-After`
+    /// header on rate limited requests. This takes precedence over the
+    /// `retry_fn` of both the query and the [`QueryClient`]. Those only see
+    /// the number of retries, since the client doesn't know the error type.
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{*, query::QueryBuilder};
+    /// # use std::time::Duration;
+    /// # #[component]
+    /// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+    /// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+    /// enum ApiError {
+    ///     RateLimited { retry_after: Duration },
+    ///     Other(String),
+    /// }
+    /// # async fn fetch_user() -> Result<String, ApiError> { Ok(String::new()) }
+    ///
+    /// let user = QueryBuilder::new("user", fetch_user)
+    ///     .retry_fn_with_error(|retries, err| match err {
+    ///         ApiError::RateLimited { retry_after } => *retry_after,
+    ///         ApiError::Other(_) => Duration::from_secs(1 << retries.min(5)),
+    ///     })
+    ///     .use_query(cx);
+    /// # view! { cx, }
+    /// # }
+    /// ```
+    pub fn retry_fn_with_error(self, retry_fn: impl Fn(u32, &E) -> Duration + 'static) -> Self {
+        self.retry_delay_with_error(move |retries, err| Some(retry_fn(retries, err)))
+    }
+
+    /// Override the retry delay for some errors only. `retry_delay` is called
+    /// before the regular [`retry_fn`](QueryBuilder::retry_fn) and returning
+    /// `None` falls back to it, so only the errors that carry their own delay
+    /// need to be handled.
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{*, query::QueryBuilder};
+    /// # use std::time::Duration;
+    /// # #[component]
+    /// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+    /// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+    /// enum ApiError {
+    ///     TooManyRequests { retry_after: Option<Duration> },
+    ///     Other(String),
+    /// }
+    /// # async fn fetch_user() -> Result<String, ApiError> { Ok(String::new()) }
+    ///
+    /// let user = QueryBuilder::new("user", fetch_user)
+    ///     .retry_delay_with_error(|_, err| match err {
+    ///         ApiError::TooManyRequests { retry_after } => *retry_after,
+    ///         ApiError::Other(_) => None,
+    ///     })
+    ///     .use_query(cx);
+    /// # view! { cx, }
+    /// # }
+    /// ```
+    pub fn retry_delay_with_error(
+        mut self,
+        retry_delay: impl Fn(u32, &E) -> Option<Duration> + 'static,
+    ) -> Self {
+        self.options.retry_delay_with_error = Some(Rc::new(move |retries, err| {
+            retry_delay(
+                retries,
+                err.downcast_ref().expect("query error has the wrong type"),
+            )
+        }));
+        self
+    }
+
+    /// Called with the number of the retry (starting at 1) every time the
+    /// query failed and is about to be retried.
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{*, query::QueryBuilder};
+    /// # #[component]
+    /// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+    /// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+    /// # async fn fetch_user() -> Result<String, String> { Ok(String::new()) }
+    /// let retry = create_rc_signal(None);
+    /// let user = QueryBuilder::new("user", fetch_user)
+    ///     .retries(3)
+    ///     .on_retry({
+    ///         let retry = retry.clone();
+    ///         move |attempt| retry.set(Some(attempt))
+    ///     })
+    ///     .use_query(cx);
+    ///
+    /// view! { cx,
+    ///     (match *retry.get() {
+    ///         Some(attempt) => format!("Retrying (attempt {attempt} of 3)"),
+    ///         None => String::new(),
+    ///     })
+    /// }
+    /// # }
+    /// ```
+    pub fn on_retry(mut self, on_retry: impl Fn(u32) + 'static) -> Self {
+        self.options.on_retry = Some(Rc::new(on_retry));
+        self
+    }
+
+    /// Wait until the key hasn't changed for `debounce` before fetching data
+    /// for a new key. Useful for search-as-you-type, where the key changes on
+    /// every keystroke. The first fetch when the query is mounted isn't
+    /// delayed. A pending fetch is dropped if the key changes again.
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{*, query::QueryBuilder};
+    /// # use std::time::Duration;
+    /// # #[component]
+    /// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+    /// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+    /// # async fn search(query: String) -> Result<Vec<String>, String> { Ok(vec![]) }
+    /// let input = create_rc_signal(String::new());
+    /// let results = QueryBuilder::new(("search", input.rc_key()), move || search(input.get().to_string()))
+    ///     .debounce(Duration::from_millis(300))
+    ///     .use_query(cx);
+    /// # view! { cx, }
+    /// # }
+    /// ```
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.options.debounce = Some(debounce);
+        self
+    }
+
+    /// Refetch the query `interval` after the previous fetch settled, for as
+    /// long as the component is mounted and the query is enabled. The
+    /// interval counts from when the previous fetch completed rather than
+    /// from the previous tick, so a fetch that takes longer than `interval`
+    /// is never overlapped by the next one. The data stays visible while
+    /// polling.
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{*, query::QueryBuilder};
+    /// # use std::time::Duration;
+    /// # #[component]
+    /// # pub fn App<G: Html>(cx: Scope) -> View<G> {
+    /// #   provide_context(cx, QueryClient::new(ClientOptions::default()));
+    /// # async fn fetch_build_status() -> Result<String, String> { Ok(String::new()) }
+    /// let build = QueryBuilder::new("build_status", fetch_build_status)
+    ///     .refetch_interval(Duration::from_secs(5))
+    ///     .use_query(cx);
+    /// # view! { cx, }
+    /// # }
+    /// ```
+    ///
+    /// A slow fetch delays the next tick instead of overlapping it:
+    ///
+    /// ```
+    /// # #[cfg(feature = "test-util")] {
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, testing::{ManualClock, TestQueryClient}, *};
+    /// # use std::{rc::Rc, time::Duration};
+    /// # async fn fetch_build_status() -> Result<String, String> { unreachable!() }
+    /// let clock = ManualClock::default();
+    /// let test = TestQueryClient::with_options(ClientOptions {
+    ///     clock: Rc::new(clock.clone()),
+    ///     ..ClientOptions::test_defaults()
+    /// });
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, test.client());
+    ///     let _ = QueryBuilder::new("build_status", fetch_build_status)
+    ///         .refetch_interval(Duration::from_secs(5))
+    ///         .use_query(cx);
+    ///
+    ///     // The first fetch is still running when the interval passes
+    ///     clock.advance(Duration::from_secs(5));
+    ///     assert_eq!(test.pending_fetches().len(), 1);
+    ///
+    ///     // The next tick is 5 seconds after it settles
+    ///     clock.advance(Duration::from_secs(2));
+    ///     test.resolve("build_status", "Running".to_string());
+    ///     clock.advance(Duration::from_secs(4));
+    ///     assert!(test.pending_fetches().is_empty());
+    ///     clock.advance(Duration::from_secs(1));
+    ///     assert_eq!(test.pending_fetches().len(), 1);
+    /// });
+    /// # }
+    /// ```
+    pub fn refetch_interval(mut self, interval: Duration) -> Self {
+        self.options.refetch_interval = Some(interval);
+        self
+    }
+
+    /// Add an entry to the query's metadata, see
+    /// [`QueryOptions::meta`](crate::QueryOptions::meta).
+    pub fn meta(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        let meta = self.options.meta.get_or_insert_with(Rc::default);
+        Rc::make_mut(meta).insert(key.into(), value.into());
+        self
+    }
+
+    /// Only run the query while `enabled` returns `true`. Signals used in the
+    /// function are tracked, so this can be used for queries that depend on
+    /// the result of another query. A disabled query keeps its current data
+    /// and can still be fetched manually with `refetch`. If it has no data,
+    /// it's [`QueryData::Idle`] instead of [`QueryData::Loading`].
+    pub fn enabled(mut self, enabled: impl Fn() -> bool + 'a) -> Self {
+        self.enabled = Some(Box::new(enabled));
+        self
+    }
+
+    /// Transform the query data before it's returned by the hook. Only this
+    /// hook sees the transformed data, the cache and other queries with the
+    /// same key keep the data returned by the fetcher.
+    pub fn select<V>(self, select: impl Fn(&U) -> V + 'a) -> QueryBuilder<'a, K, T, E, F, V> {
+        let previous = self.select;
+        QueryBuilder {
+            key: self.key,
+            fetcher: self.fetcher,
+            options: self.options,
+            enabled: self.enabled,
+            select: Box::new(move |data| Rc::new(select(&previous(data)))),
+            client: self.client,
+            _error: PhantomData,
+        }
+    }
+
+    /// Use `client` instead of the [`QueryClient`] provided as a context. This
+    /// allows for multiple independent caches, see
+    /// [Multiple Clients](crate#multiple-clients).
+    pub fn client(mut self, client: Rc<QueryClient>) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Use the query. See [`use_query`] for details.
+    pub fn use_query(self, cx: Scope<'a>) -> Query<'a, U, E, impl Fn() + 'a> {
+        let fetcher = Rc::new(move |_| (self.fetcher)());
+        use_query_inner(
+            cx,
+            self.key,
+            move |_, key| erase_fetcher(key, fetcher.clone()),
+            self.options,
+            self.enabled,
+            self.select,
+            self.client,
+        )
+    }
+}
+
+/// The shared implementation of all query hooks. `make_fetcher` creates the
+/// fetcher for a key the first time a hook uses it.
+fn use_query_inner<'a, K, T, E, U>(
+    cx: Scope<'a>,
+    key: K,
+    make_fetcher: impl Fn(&QueryClient, &QueryKey) -> Fetcher + 'a,
+    options: QueryOptions,
+    enabled: Option<Box<dyn Fn() -> bool + 'a>>,
+    select: impl Fn(Rc<T>) -> Rc<U> + 'a,
+    client: Option<Rc<QueryClient>>,
+) -> Query<'a, U, E, impl Fn() + 'a>
+where
+    K: AsKeys + 'a,
+    T: 'static,
+    E: 'static,
+    U: 'a,
+{
+    let key = create_ref(cx, key);
     let id = create_selector(cx, move || key.as_keys());
 
-    let client = use_context::<Rc<QueryClient>>(cx).clone();
-    let (data, status, fetcher) = if let Some(query) = client.find_query(&id.get(), true) {
-        query
-    } else {
-        let data: Rc<DataSignal> = as_rc(create_rc_signal(QueryData::Loading));
-        let status = as_rc(create_rc_signal(Status::Idle));
-        let fetcher: Fetcher = Rc::new(move || {
-            let fut = fetcher();
-            Box::pin(async move {
-                fut.await
-                    .map(|data| -> Rc<dyn Any> { Rc::new(data) })
-                    .map_err(|err| -> Rc<dyn Any> { Rc::new(err) })
+    let client = client.unwrap_or_else(|| QueryClient::from_context(cx, "use_query"));
+    // Created first so `find_query` can record a cache hit
+    let data_source = {
+        let client = client.clone();
+        let source = create_memo(cx, move || client.data_source_signal(&id.get()));
+        create_selector(cx, move || *source.get().get())
+    };
+    // The signals of the current key. When the key changes, the hook switches
+    // to the signals of the new key, so a fetch for the old key that's still
+    // in flight can only ever update the old key's data.
+    let meta = options.meta.clone();
+    let query = {
+        let client = client.clone();
+        create_memo(cx, move || {
+            let id = id.get();
+            untrack(|| {
+                client.register_type::<T>(&id);
+                if let Some(meta) = &meta {
+                    client.set_query_meta(&id, meta.clone());
+                }
+                if let Some(query) = client.find_query(&id, true) {
+                    return query;
+                }
+                let state: Rc<StateSignal> =
+                    Rc::new(create_rc_signal((QueryData::Loading, Status::Idle)));
+                let fetcher = make_fetcher(&client, &id);
+                client.insert_query(key, id.as_ref().clone(), state.clone(), fetcher.clone());
+                (state, fetcher)
             })
-        });
-        client.insert_query(
-            id.get().as_ref().clone(),
-            data.clone(),
-            status.clone(),
-            fetcher.clone(),
-        );
-        (data, status, fetcher)
+        })
     };
 
+    // Count the hook as an observer of its current key, so the cached data
+    // only starts expiring once nothing uses it anymore, and its options are
+    // taken into account by fetches for the key
+    let options = Rc::new(options);
+    {
+        let observed: Rc<RefCell<Option<Rc<QueryKey>>>> = Rc::default();
+        let lifetime = client.default_options.merge(&options).cache_expiration;
+        {
+            let client = client.clone();
+            let observed = observed.clone();
+            let options = options.clone();
+            create_effect(cx, move || {
+                let id = id.get();
+                let previous = observed.replace(Some(id.clone()));
+                {
+                    let mut cache = client.cache.write().unwrap();
+                    cache.observe(&id, lifetime);
+                    if let Some(previous) = &previous {
+                        cache.unobserve(previous);
+                    }
+                }
+                client.add_observer(&id, options.clone());
+                if let Some(previous) = &previous {
+                    client.remove_observer(previous, &options);
+                }
+                untrack(|| client.cache_changed());
+            });
+        }
+        let client = client.clone();
+        let options = options.clone();
+        on_cleanup(cx, move || {
+            if let Some(id) = observed.take() {
+                client.cache.write().unwrap().unobserve(&id);
+                client.remove_observer(&id, &options);
+                client.cache_changed();
+            }
+        });
+    }
+
+    let enabled = create_ref(cx, move || enabled.as_ref().is_none_or(|enabled| enabled()));
     {
         let client = client.clone();
-        let data = data.clone();
-        let status = status.clone();
+        let options = options.clone();
+        // Incremented on every key change, so a debounced fetch can tell if
+        // the key changed again while it was waiting
+        let generation = Rc::new(Cell::new(0u64));
+        let mut first_run = true;
         create_effect(cx, move || {
-            log::info!("Key changed. New key: {:?}", id.get());
-            client.clone().run_query(
-                &id.get(),
-                data.clone(),
-                status.clone(),
-                fetcher.clone(),
-                &options,
-            );
+            let (state, fetcher) = query.get().as_ref().clone();
+            untrack(|| client.register_key_repr(&id.get(), key));
+            client.log(|logger| logger.key_changed(&client.key_repr(&id.get())));
+            generation.set(generation.get() + 1);
+            if !enabled() {
+                return;
+            }
+            match options.debounce {
+                Some(debounce)
+                    if !first_run
+                        && !client.default_options.ssr
+                        && !client.default_options.skip_delays =>
+                {
+                    let client = client.clone();
+                    let id = id.get();
+                    let options = options.clone();
+                    let current = generation.get();
+                    let generation = generation.clone();
+                    client.clone().spawn(async move {
+                        timer::delay(debounce).await;
+                        if generation.get() == current {
+                            client.run_query(&id, state, fetcher, &options);
+                        }
+                    });
+                }
+                _ => {
+                    first_run = false;
+                    client.run_query(&id.get(), state, fetcher, &options);
+                }
+            }
         });
     }
 
-    let refetch = create_ref(cx, move || {
-        client.clone().refetch_query(&id.get());
-    });
-    let data = create_memo(cx, move || match data.get().as_ref() {
-        QueryData::Loading => QueryData::Loading,
-        QueryData::Ok(data) => QueryData::Ok(data.clone().downcast().unwrap()),
-        QueryData::Err(err) => QueryData::Err(err.clone().downcast().unwrap()),
+    if let Some(interval) = options.refetch_interval.filter(|_| client.polls()) {
+        // The key to poll, `None` while the query is disabled. Dropped with
+        // the scope, which ends the loop.
+        let polled: Rc<RefCell<Option<Rc<QueryKey>>>> = Rc::default();
+        {
+            let polled = polled.clone();
+            create_effect(cx, move || {
+                *polled.borrow_mut() = enabled().then(|| id.get());
+            });
+        }
+        let polled = Rc::downgrade(&polled);
+        let client = client.clone();
+        let options = options.clone();
+        // Started right away, so the first tick counts from the mount rather
+        // than from whenever the loop first runs
+        let mut tick = client.poll_delay(interval);
+        client.clone().spawn(async move {
+            loop {
+                tick.await;
+                let Some(polled) = polled.upgrade() else {
+                    return;
+                };
+                let id = polled.borrow().clone();
+                if let Some(id) = id {
+                    // Resolves once the fetch settles, including one that was
+                    // already running, so the next tick can't overlap it
+                    client.refetch_query(&id, &options, true).await;
+                }
+                tick = client.poll_delay(interval);
+            }
+        });
+    }
+
+    let state = {
+        let client = client.clone();
+        // The erased data `select` last ran on and its result, so a change of
+        // status alone doesn't run it again
+        let mut selected = (QueryData::Loading, QueryData::Loading);
+        create_memo(cx, move || {
+            let (erased, status) = query.get().0.get().as_ref().clone();
+            let data = if same_data(&selected.0, &erased) {
+                selected.1.clone()
+            } else {
+                client
+                    .downcast_data::<T, E>(&id.get_untracked(), &erased)
+                    .map(&select)
+            };
+            selected = (erased, data.clone());
+            match data {
+                QueryData::Loading if !enabled() && status != Status::Fetching => {
+                    (QueryData::Idle, status)
+                }
+                data => (data, status),
+            }
+        })
+    };
+    // Selectors, so writes that don't change anything (like marking a query
+    // that's already settled as settled again) don't rerun observers
+    let status = create_selector(cx, move || state.get().1);
+    let data = create_selector_with(cx, move || state.get().0.clone(), same_data);
+    let refetch = {
+        let client = client.clone();
+        let options = options.clone();
+        create_ref(cx, move || {
+            client.refetch_query(&id.get(), &options, false);
+        })
+    };
+    let force_refetch = {
+        let client = client.clone();
+        let options = options.clone();
+        create_ref(cx, move || {
+            client.refetch_query(&id.get(), &options, true);
+        })
+    };
+    let refetch_async = create_ref(cx, move || -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(client.refetch_query(&id.get(), &options, false))
     });
 
     Query {
         data,
         status,
+        state,
         refetch,
+        force_refetch,
+        refetch_async,
+        data_source,
     }
 }
@@ -0,0 +1,126 @@
+use std::{
+    any::Any,
+    future::Future,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use fnv::FnvHashMap;
+
+use crate::{timer::Instant, AsKeys, QueryClient, QueryKey};
+
+type SyncValue = Arc<dyn Any + Send + Sync>;
+type Hydrate = fn(&QueryClient, QueryKey, &(dyn Any + Send + Sync), Instant);
+
+/// A thread safe store for query data prefetched on a multi-threaded server.
+///
+/// The [`QueryClient`] holds reactive signals, which can't be shared between
+/// threads, so it can't be used from something like an `axum` handler
+/// directly. Instead, prefetch the data a page needs into a `SyncPrefetch`
+/// (which is `Send + Sync` and cheap to clone), then create a client for the
+/// render and [`hydrate`](SyncPrefetch::hydrate) it. The data keeps the age
+/// it had when it was prefetched. Only available with the `sync` feature.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::{*, sync::SyncPrefetch};
+/// let prefetch = SyncPrefetch::default();
+/// std::thread::spawn({
+///     let prefetch = prefetch.clone();
+///     move || prefetch.insert(("user", 1), "Alice".to_string())
+/// })
+/// .join()
+/// .unwrap();
+///
+/// // On the thread that renders the page
+/// let client = QueryClient::new(ClientOptions::default());
+/// prefetch.hydrate(&client);
+/// assert_eq!(
+///     client.query_data::<_, String>(("user", 1)).as_deref(),
+///     Some(&"Alice".to_string())
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct SyncPrefetch(Arc<Mutex<FnvHashMap<QueryKey, SyncEntry>>>);
+
+#[derive(Clone)]
+struct SyncEntry {
+    value: SyncValue,
+    fetched_at: Instant,
+    #[cfg(feature = "readable-keys")]
+    repr: Option<String>,
+    /// Converts the value back into the type the queries expect
+    hydrate: Hydrate,
+}
+
+impl SyncPrefetch {
+    /// Store data for `key` that was fetched just now, replacing any data
+    /// that was stored for it before.
+    pub fn insert<K: AsKeys, T: Clone + Send + Sync + 'static>(&self, key: K, value: T) {
+        let entry = SyncEntry {
+            value: Arc::new(value),
+            fetched_at: Instant::now(),
+            #[cfg(feature = "readable-keys")]
+            repr: key.key_repr(),
+            hydrate: hydrate::<T>,
+        };
+        self.0.lock().unwrap().insert(key.as_keys(), entry);
+    }
+
+    /// Run `fetch` and store its data for `key` if it succeeds. Errors aren't
+    /// stored, so the query fetches again on the client.
+    pub async fn prefetch<K, T, E, R>(&self, key: K, fetch: R) -> Result<(), E>
+    where
+        K: AsKeys,
+        T: Clone + Send + Sync + 'static,
+        R: Future<Output = Result<T, E>>,
+    {
+        let value = fetch.await?;
+        self.insert(key, value);
+        Ok(())
+    }
+
+    /// Put all stored data into `client`'s cache, like
+    /// [`QueryClient::hydrate_query_data`] does for a single query.
+    pub fn hydrate(&self, client: &QueryClient) {
+        // Cloned first so the lock isn't held while the client notifies
+        // subscribers
+        let entries: Vec<_> = self
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+        for (key, entry) in entries {
+            #[cfg(feature = "readable-keys")]
+            if let Some(repr) = entry.repr {
+                client
+                    .key_reprs
+                    .write()
+                    .unwrap()
+                    .entry(key.clone())
+                    .or_insert(repr);
+            }
+            (entry.hydrate)(client, key, entry.value.as_ref(), entry.fetched_at);
+        }
+    }
+}
+
+fn hydrate<T: Clone + 'static>(
+    client: &QueryClient,
+    key: QueryKey,
+    value: &(dyn Any + Send + Sync),
+    fetched_at: Instant,
+) {
+    let age = fetched_at.elapsed();
+    if age > client.default_options.cache_expiration {
+        return;
+    }
+    let Some(value) = value.downcast_ref::<T>() else {
+        return;
+    };
+    client.register_type::<T>(&key);
+    client.hydrate_erased_query_data(key, Rc::new(value.clone()), age);
+}
@@ -0,0 +1,372 @@
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    future::{poll_fn, Future},
+    mem,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use crate::{timer::Clock, AsKeys, ClientOptions, FetchFuture, QueryClient, QueryKey};
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+type FetchResult = Result<Rc<dyn Any>, Rc<dyn Any>>;
+/// Where the test puts the result of a pending fetch.
+type FetchSlot = Rc<RefCell<Option<FetchResult>>>;
+
+/// A [`QueryClient`] for tests, whose fetches are resolved by the test
+/// instead of running the fetchers.
+///
+/// Everything the client would run in the background, like fetches and
+/// mutations, is queued and only runs when the test calls
+/// [`flush`](TestQueryClient::flush), [`resolve`](TestQueryClient::resolve)
+/// or [`reject`](TestQueryClient::reject). No network is needed and it works
+/// outside the browser, so tests can use plain `#[test]` functions. Provide
+/// [`client`](TestQueryClient::client) as the context for the components
+/// under test.
+///
+/// [`new`](TestQueryClient::new) uses [`ClientOptions::test_defaults`], so
+/// there are no retries and nothing waits on a real timer, which
+/// [`flush`](TestQueryClient::flush) wouldn't wait for. Refetch intervals
+/// wait for the client's [`Clock`] instead, so a [`ManualClock`] drives them.
+/// Only available with the `test-util` feature.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, testing::TestQueryClient, AsKeys};
+/// # use std::rc::Rc;
+/// # async fn fetch_user(id: u32) -> Result<String, String> { unreachable!() }
+/// let test = TestQueryClient::new();
+/// create_scope_immediate(|cx| {
+///     provide_context(cx, test.client());
+///     let Query { data, .. } = use_query(cx, ("user", 1), || fetch_user(1));
+///     assert_eq!(*data.get(), QueryData::Loading);
+///
+///     assert_eq!(test.pending_fetches(), vec![("user", 1).as_keys()]);
+///     test.resolve(("user", 1), "Alice".to_string());
+///     assert_eq!(*data.get(), QueryData::Ok(Rc::new("Alice".to_string())));
+/// });
+/// ```
+pub struct TestQueryClient {
+    client: Rc<QueryClient>,
+    env: Rc<TestEnv>,
+}
+
+impl TestQueryClient {
+    /// Creates a test client with [`ClientOptions::test_defaults`].
+    pub fn new() -> Self {
+        Self::with_options(ClientOptions::test_defaults())
+    }
+
+    /// Creates a test client with `options`. Server side rendering is
+    /// always turned off, so queries fetch like they do in the browser.
+    pub fn with_options(options: ClientOptions) -> Self {
+        let client = QueryClient::new(ClientOptions {
+            ssr: false,
+            ..options
+        });
+        let env = Rc::new(TestEnv::default());
+        if client.test_env.set(env.clone()).is_err() {
+            unreachable!("the client was just created");
+        }
+        client.record_fetches();
+        Self { client, env }
+    }
+
+    /// The client, to be provided as context for the components under test.
+    pub fn client(&self) -> Rc<QueryClient> {
+        self.client.clone()
+    }
+
+    /// Run queued background work until nothing can make progress without
+    /// the test resolving a fetch.
+    pub fn flush(&self) {
+        self.env.flush();
+    }
+
+    /// The keys of all fetches waiting to be resolved, in the order they
+    /// started. Flushes first, so fetches that were just queued are
+    /// included.
+    pub fn pending_fetches(&self) -> Vec<QueryKey> {
+        self.flush();
+        self.env
+            .pending
+            .borrow()
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Resolve all pending fetches for `key` with `data`, then flush.
+    /// `data` must have the type the queries for `key` expect. Returns
+    /// whether there was a pending fetch.
+    pub fn resolve<K: AsKeys, T: 'static>(&self, key: K, data: T) -> bool {
+        self.settle(key.as_keys(), Ok(Rc::new(data)))
+    }
+
+    /// Fail all pending fetches for `key` with `err`, then flush. `err` must
+    /// have the error type the queries for `key` expect. Returns whether
+    /// there was a pending fetch.
+    pub fn reject<K: AsKeys, E: 'static>(&self, key: K, err: E) -> bool {
+        self.settle(key.as_keys(), Err(Rc::new(err)))
+    }
+
+    fn settle(&self, key: QueryKey, result: FetchResult) -> bool {
+        self.flush();
+        let settled = {
+            let mut pending = self.env.pending.borrow_mut();
+            let (settled, rest) = mem::take(&mut *pending)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(pending, _)| *pending == key);
+            *pending = rest;
+            settled
+        };
+        for (_, slot) in &settled {
+            *slot.borrow_mut() = Some(result.clone());
+        }
+        self.flush();
+        !settled.is_empty()
+    }
+}
+
+impl Default for TestQueryClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether a [`FetchRecord`] is for a query or a mutation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FetchKind {
+    /// A query fetch, including refetches and prefetches
+    Query,
+    /// A mutation
+    Mutation,
+}
+
+/// How a recorded fetch ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FetchOutcome {
+    /// The fetch hasn't settled yet
+    Pending,
+    /// The fetch succeeded
+    Success,
+    /// The fetch failed after all retries
+    Error,
+}
+
+/// A fetch or mutation the client ran, see [`QueryClient::fetch_log`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetchRecord {
+    /// The key of the query, or the
+    /// [`mutation_key`](crate::MutationOptions::mutation_key) of the mutation
+    pub key: Option<QueryKey>,
+    /// Whether this was a query or a mutation
+    pub kind: FetchKind,
+    /// When the fetch started, according to the client's [`Clock`]
+    pub started_at: Duration,
+    /// How the fetch ended
+    pub outcome: FetchOutcome,
+    /// How often the fetcher was called, including retries. 0 while pending.
+    pub attempts: u32,
+}
+
+pub(crate) type FetchRecordHandle = Option<Rc<RefCell<FetchRecord>>>;
+
+impl QueryClient {
+    /// Start recording every fetch and mutation the client runs, clearing
+    /// anything recorded so far. A [`TestQueryClient`] records from the
+    /// start. Only available with the `test-util` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use sycamore::prelude::*;
+    /// # use sycamore_query::{prelude::*, testing::*, *};
+    /// # async fn fetch_todos(list: u32) -> Result<Vec<String>, String> { unreachable!() }
+    /// let test = TestQueryClient::new();
+    /// let client = test.client();
+    /// create_scope_immediate(|cx| {
+    ///     provide_context(cx, client.clone());
+    ///     use_query(cx, ("todos", 1), || fetch_todos(1));
+    ///     use_query(cx, ("todos", 2), || fetch_todos(2));
+    ///     test.resolve(("todos", 1), Vec::<String>::new());
+    ///     test.resolve(("todos", 2), Vec::<String>::new());
+    ///
+    ///     client.record_fetches();
+    ///     client.invalidate_queries("todos");
+    ///     test.flush();
+    ///     let log = client.fetch_log();
+    ///     assert_eq!(log.len(), 2);
+    ///     assert!(log.iter().all(|record| record.outcome == FetchOutcome::Pending));
+    /// });
+    /// ```
+    pub fn record_fetches(&self) {
+        *self.fetch_log.borrow_mut() = Some(Vec::new());
+    }
+
+    /// The fetches and mutations recorded since
+    /// [`record_fetches`](QueryClient::record_fetches), in the order they
+    /// started. Empty if the client isn't recording. Only available with the
+    /// `test-util` feature.
+    pub fn fetch_log(&self) -> Vec<FetchRecord> {
+        self.fetch_log
+            .borrow()
+            .iter()
+            .flatten()
+            .map(|record| record.borrow().clone())
+            .collect()
+    }
+
+    /// Record that a fetch started, if the client is recording.
+    pub(crate) fn record_fetch_started(
+        &self,
+        key: Option<QueryKey>,
+        kind: FetchKind,
+    ) -> FetchRecordHandle {
+        let mut log = self.fetch_log.borrow_mut();
+        let record = Rc::new(RefCell::new(FetchRecord {
+            key,
+            kind,
+            started_at: self.default_options.clock.now(),
+            outcome: FetchOutcome::Pending,
+            attempts: 0,
+        }));
+        log.as_mut()?.push(record.clone());
+        Some(record)
+    }
+
+    /// Record that the fetch for `handle` settled.
+    pub(crate) fn record_fetch_settled(handle: FetchRecordHandle, attempts: u32, success: bool) {
+        if let Some(record) = handle {
+            let mut record = record.borrow_mut();
+            record.attempts = attempts;
+            record.outcome = if success {
+                FetchOutcome::Success
+            } else {
+                FetchOutcome::Error
+            };
+        }
+    }
+}
+
+/// A [`Clock`] that only moves when the test
+/// [`advance`](ManualClock::advance)s it, for testing expiration and
+/// staleness without waiting. Clones share the same time. Set it with
+/// [`ClientOptions::clock`].
+///
+/// # Example
+///
+/// ```
+/// # use sycamore::prelude::*;
+/// # use sycamore_query::{prelude::*, testing::{ManualClock, TestQueryClient}, *};
+/// # use std::{rc::Rc, time::Duration};
+/// # async fn fetch_user(id: u32) -> Result<String, String> { unreachable!() }
+/// let clock = ManualClock::default();
+/// let test = TestQueryClient::with_options(ClientOptions {
+///     clock: Rc::new(clock.clone()),
+///     stale_time: Duration::from_secs(60),
+///     ..ClientOptions::test_defaults()
+/// });
+/// test.client().set_query_data(("user", 1), "Alice".to_string());
+///
+/// create_scope_immediate(|cx| {
+///     provide_context(cx, test.client());
+///     let Query { data, .. } = use_query(cx, ("user", 1), || fetch_user(1));
+///     assert!(matches!(*data.get(), QueryData::Ok(_)));
+///     assert!(test.pending_fetches().is_empty());
+/// });
+///
+/// // The cache expires 5 minutes after the last query using it unmounted
+/// clock.advance(Duration::from_secs(6 * 60));
+/// create_scope_immediate(|cx| {
+///     provide_context(cx, test.client());
+///     let Query { data, .. } = use_query(cx, ("user", 1), || fetch_user(1));
+///     assert_eq!(*data.get(), QueryData::Loading);
+///     assert_eq!(test.pending_fetches(), vec![("user", 1).as_keys()]);
+/// });
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock(Rc<Cell<Duration>>);
+
+impl ManualClock {
+    /// Move the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.0.set(self.0.get() + duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.0.get()
+    }
+}
+
+/// Wait for the browser's microtask queue to run, so promises and
+/// `spawn_local` tasks started by the components under test can make
+/// progress. Only available on `wasm32`, for use with `wasm-bindgen-test`.
+/// Work queued by a [`TestQueryClient`] still needs
+/// [`flush`](TestQueryClient::flush).
+#[cfg(target_arch = "wasm32")]
+pub async fn flush_microtasks() {
+    let promise = js_sys::Promise::resolve(&wasm_bindgen::JsValue::NULL);
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+/// The queue of background work and pending fetches of a
+/// [`TestQueryClient`].
+#[derive(Default)]
+pub(crate) struct TestEnv {
+    tasks: RefCell<Vec<Task>>,
+    pending: RefCell<Vec<(QueryKey, FetchSlot)>>,
+}
+
+impl TestEnv {
+    pub(crate) fn spawn(&self, future: impl Future<Output = ()> + 'static) {
+        self.tasks.borrow_mut().push(Box::pin(future));
+    }
+
+    /// A fetch for `key` that completes once the test settles it.
+    pub(crate) fn pending_fetch(&self, key: QueryKey) -> FetchFuture {
+        let slot = FetchSlot::default();
+        self.pending.borrow_mut().push((key, slot.clone()));
+        Box::pin(poll_fn(move |_| match slot.borrow_mut().take() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }))
+    }
+
+    /// Poll every task until a full pass completes none of them, spawns
+    /// nothing new and starts no fetches. Tasks are polled without being
+    /// woken, so this also catches tasks waiting on something a previous task
+    /// just did.
+    fn flush(&self) {
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            let tasks = mem::take(&mut *self.tasks.borrow_mut());
+            if tasks.is_empty() {
+                return;
+            }
+            let pending = self.pending.borrow().len();
+            let mut progress = false;
+            let mut waiting = Vec::new();
+            for mut task in tasks {
+                match task.as_mut().poll(&mut cx) {
+                    Poll::Ready(()) => progress = true,
+                    Poll::Pending => waiting.push(task),
+                }
+            }
+            let mut tasks = self.tasks.borrow_mut();
+            progress |= !tasks.is_empty() || self.pending.borrow().len() != pending;
+            waiting.append(&mut tasks);
+            *tasks = waiting;
+            if !progress {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+/// Waits for a duration. Used for retry delays and debouncing.
+///
+/// Which implementation the library uses is picked at compile time by the
+/// enabled features, see [`DefaultTimer`]:
+///
+/// * `wasm-timer` (default) - `WasmTimer`, for the browser.
+/// * `tokio` - `TokioTimer`. Needs a running `tokio` runtime with the time
+///   driver enabled. Takes precedence over `wasm-timer` on native targets.
+/// * `async-std` - `AsyncStdTimer`. Takes precedence over `wasm-timer` on
+///   native targets.
+/// * Without any of them, [`ThreadTimer`], which doesn't need a runtime and
+///   works anywhere threads are available.
+pub trait Timer {
+    /// Returns a future that resolves once `duration` has passed.
+    fn delay(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>>;
+}
+
+/// A [`Timer`] using `fluvio-wasm-timer`, which works in the browser.
+#[cfg(feature = "wasm-timer")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WasmTimer;
+
+#[cfg(feature = "wasm-timer")]
+impl Timer for WasmTimer {
+    fn delay(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(async move {
+            // Only fails if the timer was dropped, at which point there's nothing
+            // left to wait for
+            let _ = fluvio_wasm_timer::Delay::new(duration).await;
+        })
+    }
+}
+
+/// A [`Timer`] using `tokio::time::sleep`.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioTimer;
+
+#[cfg(feature = "tokio")]
+impl Timer for TokioTimer {
+    fn delay(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// A [`Timer`] using `async_std::task::sleep`.
+#[cfg(feature = "async-std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStdTimer;
+
+#[cfg(feature = "async-std")]
+impl Timer for AsyncStdTimer {
+    fn delay(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+/// A [`Timer`] that sleeps on a background thread and wakes the task when it's
+/// done. Doesn't depend on any runtime, but spawns a thread per delay, so it's
+/// mostly useful for tests. Not available in the browser.
+///
+/// # Example
+///
+/// ```
+/// # use sycamore_query::timer::{ThreadTimer, Timer};
+/// # use std::{future::Future, sync::Arc, task::{Context, Poll, Wake}, thread::{self, Thread}};
+/// # use std::time::{Duration, Instant};
+/// # struct Unpark(Thread);
+/// # impl Wake for Unpark {
+/// #     fn wake(self: Arc<Self>) { self.0.unpark() }
+/// # }
+/// # fn block_on<F: Future>(future: F) -> F::Output {
+/// #     let waker = Arc::new(Unpark(thread::current())).into();
+/// #     let mut cx = Context::from_waker(&waker);
+/// #     let mut future = Box::pin(future);
+/// #     loop {
+/// #         match future.as_mut().poll(&mut cx) {
+/// #             Poll::Ready(output) => return output,
+/// #             Poll::Pending => thread::park(),
+/// #         }
+/// #     }
+/// # }
+/// let start = Instant::now();
+/// block_on(ThreadTimer.delay(Duration::from_millis(20)));
+/// assert!(start.elapsed() >= Duration::from_millis(20));
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadTimer;
+
+impl Timer for ThreadTimer {
+    fn delay(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(ThreadDelay {
+            duration: Some(duration),
+            state: Arc::default(),
+        })
+    }
+}
+
+#[derive(Default)]
+struct ThreadDelayState {
+    done: bool,
+    waker: Option<Waker>,
+}
+
+struct ThreadDelay {
+    /// Taken when the thread is started on the first poll
+    duration: Option<Duration>,
+    state: Arc<Mutex<ThreadDelayState>>,
+}
+
+impl Future for ThreadDelay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.done {
+                return Poll::Ready(());
+            }
+            state.waker = Some(cx.waker().clone());
+        }
+        if let Some(duration) = self.duration.take() {
+            let state = self.state.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(duration);
+                let mut state = state.lock().unwrap();
+                state.done = true;
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            });
+        }
+        Poll::Pending
+    }
+}
+
+/// The [`Timer`] selected by the enabled features.
+#[cfg(all(
+    feature = "wasm-timer",
+    any(
+        target_arch = "wasm32",
+        not(any(feature = "tokio", feature = "async-std"))
+    )
+))]
+pub type DefaultTimer = WasmTimer;
+/// The [`Timer`] selected by the enabled features.
+#[cfg(all(
+    feature = "tokio",
+    not(all(feature = "wasm-timer", target_arch = "wasm32"))
+))]
+pub type DefaultTimer = TokioTimer;
+/// The [`Timer`] selected by the enabled features.
+#[cfg(all(
+    feature = "async-std",
+    not(feature = "tokio"),
+    not(all(feature = "wasm-timer", target_arch = "wasm32"))
+))]
+pub type DefaultTimer = AsyncStdTimer;
+/// The [`Timer`] selected by the enabled features.
+#[cfg(not(any(feature = "wasm-timer", feature = "tokio", feature = "async-std")))]
+pub type DefaultTimer = ThreadTimer;
+
+/// Wait for `duration` using the [`DefaultTimer`].
+pub(crate) fn delay(duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+    DefaultTimer::default().delay(duration)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::Instant;
+
+/// A monotonic point in time. `std::time::Instant::now` panics in the
+/// browser, so this uses `performance.now()` instead, falling back to
+/// `Date.now()` where there's no `performance`. Doesn't depend on the
+/// selected [`Timer`], so the cache keeps time correctly with any of them.
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub(crate) struct Instant {
+    /// Milliseconds since the time origin of the page or worker
+    millis: f64,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Instant {
+    pub(crate) fn now() -> Self {
+        use wasm_bindgen::JsCast;
+
+        let performance = js_sys::Reflect::get(&js_sys::global(), &"performance".into())
+            .ok()
+            .and_then(|performance| performance.dyn_into::<web_sys::Performance>().ok());
+        let millis = performance.map_or_else(js_sys::Date::now, |performance| performance.now());
+        Self { millis }
+    }
+
+    /// The time since `earlier`, or zero if `earlier` is later.
+    pub(crate) fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_secs_f64((self.millis - earlier.millis).max(0.0) / 1000.0)
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+}
+
+/// The source of the current time for cache expiration, staleness and
+/// least recently used eviction. Set it with
+/// [`ClientOptions::clock`](crate::ClientOptions::clock). The default is
+/// [`SystemClock`]. Tests can use a `ManualClock` from the `testing` module
+/// (with the `test-util` feature) to control time.
+///
+/// Times are measured from an arbitrary fixed point, only the difference
+/// between two times is ever used.
+pub trait Clock {
+    /// The time since the clock's fixed point. Must never go backwards.
+    fn now(&self) -> Duration;
+}
+
+/// The default [`Clock`], using `std::time::Instant` on native targets and
+/// `performance.now()` in the browser, where `std::time::Instant` isn't
+/// available.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+thread_local! {
+    static START: Instant = Instant::now();
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        START.with(|start| Instant::now().duration_since(*start))
+    }
+}
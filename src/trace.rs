@@ -0,0 +1,41 @@
+use tracing::{field::Empty, Span};
+
+use crate::timer::Instant;
+
+/// The span covering a query fetch, including all retries.
+pub(crate) fn fetch_span(key: &str) -> Span {
+    tracing::debug_span!(
+        "fetch_query",
+        key,
+        attempts = Empty,
+        duration_ms = Empty,
+        outcome = Empty
+    )
+}
+
+/// The span covering a mutation from the moment it's sent.
+pub(crate) fn mutation_span(key: Option<&str>) -> Span {
+    tracing::debug_span!(
+        "mutation",
+        key,
+        attempts = Empty,
+        duration_ms = Empty,
+        outcome = Empty
+    )
+}
+
+/// Record the result of a fetch or mutation on its span.
+pub(crate) fn record_outcome(span: &Span, started: Instant, attempts: u32, success: bool) {
+    span.record("attempts", attempts);
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+    span.record("outcome", if success { "success" } else { "error" });
+}
+
+pub(crate) fn invalidated(key: &str) {
+    tracing::debug!(name: "invalidated", key, "query invalidated");
+}
+
+/// `result` is one of `"hit"`, `"stale"` or `"miss"`.
+pub(crate) fn cache_lookup(key: &str, result: &'static str) {
+    tracing::debug!(name: "cache_lookup", key, result, "query cache lookup");
+}